@@ -0,0 +1,188 @@
+use actix_session::Session;
+use actix_web::{
+    delete, get,
+    http::header::{ContentDisposition, DispositionParam, DispositionType},
+    web::{Data, Json, Query},
+    HttpResponse,
+};
+use log::info;
+
+use crate::{
+    api::handler::auth_routes::end_session,
+    api::handler::middleware::auth_middleware::CheckAuth,
+    api::handler::{Error, WebResult},
+    db::{
+        audit_repository::AuditRepository, poll_repository::PollRepository,
+        token_repository::TokenRepository, user_repository::UserRepository,
+    },
+    models::{
+        account_models::{
+            deleted_voter, AccountExport, DeleteAccountQuery, PollPolicy, DELETED_CREATOR,
+        },
+        auth_event::{event_limit, AuthEventEntry, HistoryQuery},
+        auth_jwt::{Claims, RecentAuth},
+        profile_models::ProfileView,
+        user_models::{normalize_username, User},
+    },
+};
+
+/// Loads the user behind the access token
+async fn current_user(db: &dyn UserRepository, claims: &Claims) -> WebResult<User> {
+    db.get_user_by_id(claims.uuid.to_string())
+        .await
+        .map_err(|e| Error::Database(e.to_string()))?
+        .ok_or(Error::Unauthorized)
+}
+
+/// The caller's recent sign-in attempts, newest first
+#[get("sign_ins", wrap = "CheckAuth")]
+pub(crate) async fn sign_in_history(
+    claims: Claims,
+    query: Query<HistoryQuery>,
+    audit_log: Data<dyn AuditRepository>,
+) -> WebResult<Json<Vec<AuthEventEntry>>> {
+    let events = audit_log
+        .sign_in_history(&claims.uuid.to_string(), event_limit(query.limit))
+        .await
+        .map_err(|e| Error::Database(e.to_string()))?;
+
+    Ok(Json(events.into_iter().map(AuthEventEntry::from).collect()))
+}
+
+/// Downloads everything stored about the caller as a JSON archive
+#[get("export", wrap = "CheckAuth")]
+pub(crate) async fn export(
+    claims: Claims,
+    db: Data<dyn UserRepository>,
+    polls: Data<dyn PollRepository>,
+) -> WebResult<HttpResponse> {
+    let user = current_user(db.as_ref(), &claims).await?;
+    let owned_polls = user.owned_polls.clone().unwrap_or_default();
+
+    let poll_contents = polls
+        .get_polls(owned_polls.clone())
+        .await
+        .map_err(|e| Error::Database(e.to_string()))?;
+
+    let archive = AccountExport {
+        exported_at: chrono::Utc::now(),
+        credentials: user.credential_infos(),
+        profile: ProfileView::from(&user),
+        recovery_codes_remaining: user.recovery_codes.len(),
+        polls_voted: user.polls_voted.unwrap_or_default(),
+        owned_polls,
+        polls: poll_contents,
+        user_id: user.user_id,
+        user_name: user.user_name,
+        roles: user.roles,
+    };
+
+    info!("Exported account data for user {}", archive.user_id);
+    Ok(HttpResponse::Ok()
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(
+                "account-export.json".to_string(),
+            )],
+        })
+        .json(archive))
+}
+
+/// Deletes the caller's account
+///
+/// Their polls are transferred, anonymized or deleted according to `?polls=`,
+/// and their name is replaced by a tombstone in every poll they voted in. The
+/// name stays reserved, so their votes cannot be cast again under it.
+#[delete("", wrap = "CheckAuth")]
+pub(crate) async fn delete_account(
+    RecentAuth(claims): RecentAuth,
+    query: Query<DeleteAccountQuery>,
+    session: Session,
+    db: Data<dyn UserRepository>,
+    polls: Data<dyn PollRepository>,
+    tokens: Data<dyn TokenRepository>,
+) -> WebResult<HttpResponse> {
+    let DeleteAccountQuery {
+        polls: policy,
+        transfer_to,
+    } = query.into_inner();
+    let user = current_user(db.as_ref(), &claims).await?;
+    let owned_polls = user.owned_polls.clone().unwrap_or_default();
+
+    // Resolve the recipient before anything is changed
+    let recipient = match (policy, transfer_to) {
+        (PollPolicy::Transfer, Some(name)) => {
+            let name = normalize_username(&name).map_err(|e| Error::InvalidInput(e.to_string()))?;
+            let recipient = db
+                .get_user(name)
+                .await
+                .map_err(|e| Error::Database(e.to_string()))?
+                .ok_or(Error::UserNotFound)?;
+            if recipient.user_id == user.user_id {
+                return Err(Error::InvalidInput(
+                    "Polls cannot be transferred to the account being deleted".to_string(),
+                ));
+            }
+            Some(recipient)
+        }
+        (PollPolicy::Transfer, None) => {
+            return Err(Error::InvalidInput(
+                "transfer_to is required to transfer polls".to_string(),
+            ))
+        }
+        _ => None,
+    };
+
+    if !owned_polls.is_empty() {
+        match (policy, recipient) {
+            (PollPolicy::Transfer, Some(recipient)) => {
+                polls
+                    .reassign_polls(owned_polls.clone(), recipient.user_name)
+                    .await
+                    .map_err(|e| Error::Database(e.to_string()))?;
+                db.add_owned_polls(recipient.user_id, owned_polls)
+                    .await
+                    .map_err(|e| Error::Database(e.to_string()))?;
+            }
+            (PollPolicy::Delete, _) => polls
+                .delete_polls(owned_polls)
+                .await
+                .map_err(|e| Error::Database(e.to_string()))?,
+            _ => polls
+                .reassign_polls(owned_polls, DELETED_CREATOR.to_string())
+                .await
+                .map_err(|e| Error::Database(e.to_string()))?,
+        }
+    }
+
+    // Reserve the name first, so no new account can take it and vote again
+    db.reserve_username(user.user_name.clone())
+        .await
+        .map_err(|e| Error::Database(e.to_string()))?;
+    polls
+        .anonymize_voter(user.user_name.clone(), deleted_voter(&user.user_id))
+        .await
+        .map_err(|e| Error::Database(e.to_string()))?;
+
+    tokens
+        .revoke_user_refresh_tokens(&user.user_id)
+        .await
+        .map_err(|e| Error::Database(e.to_string()))?;
+    tokens
+        .revoke_all_access_tokens(&user.user_id)
+        .await
+        .map_err(|e| Error::Database(e.to_string()))?;
+    tokens
+        .revoke_user_api_tokens(&user.user_id)
+        .await
+        .map_err(|e| Error::Database(e.to_string()))?;
+
+    db.delete_user(user.user_id.clone())
+        .await
+        .map_err(|e| Error::Database(e.to_string()))?;
+
+    info!("Deleted account {} ({:?} polls)", user.user_id, policy);
+    let mut response = HttpResponse::Ok().json("Account deleted");
+    end_session(&session, &mut response);
+    Ok(response)
+}
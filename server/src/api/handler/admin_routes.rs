@@ -0,0 +1,41 @@
+use actix_web::{
+    get,
+    web::{Data, Json, Query},
+};
+
+use crate::{
+    api::handler::middleware::auth_middleware::CheckAuth,
+    api::handler::{Error, WebResult},
+    db::audit_repository::AuditRepository,
+    models::{
+        auth_event::{event_limit, AuthEventEntry, AuthEventQuery},
+        auth_jwt::Claims,
+        user_models::Role,
+    },
+};
+
+/// Searches the authentication audit log
+///
+/// Filters: `user_id`, `username`, `event_type`, `outcome`, `ip`, `since`, `until`
+/// (RFC 3339) and `limit`.
+#[get("auth_events", wrap = "CheckAuth")]
+pub(crate) async fn auth_events(
+    claims: Claims,
+    query: Query<AuthEventQuery>,
+    audit_log: Data<dyn AuditRepository>,
+) -> WebResult<Json<Vec<AuthEventEntry>>> {
+    if !claims.has_role(Role::Admin) {
+        return Err(Error::Forbidden(
+            "Only admins can read the audit log".to_string(),
+        ));
+    }
+
+    let query = query.into_inner();
+    let limit = event_limit(query.limit);
+    let events = audit_log
+        .query(query, limit)
+        .await
+        .map_err(|e| Error::Database(e.to_string()))?;
+
+    Ok(Json(events.into_iter().map(AuthEventEntry::from).collect()))
+}
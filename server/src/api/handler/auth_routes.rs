@@ -1,91 +1,412 @@
+use actix_session::Session;
 use actix_web::{
-    post,
-    web::{Data, Json, Path},
-    HttpResponse,
+    delete, get, patch, post,
+    web::{Data, Json, Path, Query},
+    HttpRequest, HttpResponse,
 };
-use log::{error, info};
-use serde::Serialize;
+use jsonwebtoken::jwk::JwkSet;
+use log::{error, info, warn};
+use mongodb::bson::DateTime;
+use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 use webauthn_rs::prelude::*;
+use webauthn_rs_proto::UserVerificationPolicy;
 
 use crate::{
+    api::handler::middleware::auth_middleware::CheckAuth,
     api::handler::{Error, WebResult},
-    db::user_repository::UserRepository,
+    db::{
+        audit_repository::AuditRepository, challenge_store::StateError,
+        token_repository::TokenRepository, user_repository::UserRepository,
+    },
     models::{
-        auth_jwt::encode_jwt, authentication_state::AuthenticationState,
-        registration_state::RegistrationState, user_models::User,
+        api_token_models::{
+            generate_api_token, hash_api_token, ApiToken, ApiTokenInfo, CreateApiTokenRequest,
+            CreatedApiToken, DEFAULT_API_TOKEN_TTL_DAYS, MAX_API_TOKEN_TTL_DAYS,
+        },
+        auth_event::{AuthEvent, AuthEventType},
+        auth_jwt::{
+            encode_jwt, encode_step_up_jwt, refresh_token_ttl, step_up_max_age, AuthContext,
+            Claims, RecentAuth,
+        },
+        authentication_state::{AuthenticationData, AuthenticationState},
+        jwt_keys::keys,
+        profile_models::{normalize_display_name, Profile},
+        recovery_codes::{
+            generate_recovery_codes, hash_recovery_code, RecoveryCodesResponse, RecoveryRequest,
+        },
+        registration_state::{RegistrationData, RegistrationState},
+        rp_config::RelyingPartyConfig,
+        security_event::{self, SecurityEvent},
+        session_mode::{
+            csrf_cookie, generate_csrf_token, removal_csrf_cookie, verify_csrf, SessionMode,
+            ACCESS_TOKEN_KEY, CSRF_TOKEN_KEY, REFRESH_TOKEN_KEY,
+        },
+        token_models::{
+            generate_refresh_token, hash_refresh_token, LogoutRequest, RefreshRequest, RefreshToken,
+        },
+        user_models::{normalize_username, CredentialInfo, Role, User},
     },
 };
 
+/// Session key holding the ID of the caller's in-flight registration ceremony
+const REG_CEREMONY_KEY: &str = "reg_ceremony";
+/// Session key holding the ID of the caller's in-flight authentication ceremony
+const AUTH_CEREMONY_KEY: &str = "auth_ceremony";
+/// Session key holding the ID of the caller's in-flight add-passkey ceremony
+const ADD_PASSKEY_CEREMONY_KEY: &str = "add_passkey_ceremony";
+/// Session key holding the ID of the caller's in-flight account recovery ceremony
+const RECOVERY_CEREMONY_KEY: &str = "recovery_ceremony";
+/// Session key holding the ID of the caller's in-flight step-up ceremony
+const STEP_UP_CEREMONY_KEY: &str = "step_up_ceremony";
+
 #[derive(Debug, Serialize)]
 struct AuthenticationResponse {
     token: String,
+    refresh_token: String,
+}
+
+/// Body returned on sign-in in cookie mode; the tokens themselves stay in the session cookie
+#[derive(Debug, Serialize)]
+struct CookieSessionResponse {
+    csrf_token: String,
+}
+
+/// Body returned by a step-up; cookie-mode clients get the token in their session instead
+#[derive(Debug, Serialize)]
+struct StepUpResponse {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    token: Option<String>,
+    expires_in: i64,
+}
+
+#[derive(Debug, Serialize)]
+struct CeremonyCounters {
+    pending: usize,
+    swept: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct CeremonyStatsResponse {
+    registration: CeremonyCounters,
+    authentication: CeremonyCounters,
+}
+
+/// Maps a challenge store failure onto the error returned to the client
+fn ceremony_error(err: StateError) -> Error {
+    match err {
+        StateError::Expired => Error::CeremonyExpired,
+        StateError::NotFound => Error::CeremonyNotFound,
+        StateError::Storage(e) => Error::Database(e),
+        StateError::LockError => Error::CorruptSession,
+    }
+}
+
+/// Maps a failed assertion to an error, reporting a possibly cloned authenticator
+fn assertion_error(err: WebauthnError, user_id: &str, credential_id: &str) -> Error {
+    match err {
+        WebauthnError::CredentialPossibleCompromise => {
+            security_event::report(&SecurityEvent::CounterRegression {
+                user_id: user_id.to_string(),
+                credential_id: credential_id.to_string(),
+            });
+            Error::Forbidden("This passkey may have been cloned".to_string())
+        }
+        e => Error::BadRequest(e),
+    }
+}
+
+/// Appends `event` to the audit log; a failing audit log never fails the request
+async fn audit(req: &HttpRequest, event: AuthEvent) {
+    let Some(audit_log) = req.app_data::<Data<dyn AuditRepository>>() else {
+        error!(
+            "Audit log unavailable, dropping {:?} event",
+            event.event_type
+        );
+        return;
+    };
+    if let Err(e) = audit_log.record(event).await {
+        error!("Failed to write audit event: {}", e);
+    }
+}
+
+/// Issues an access token and a refresh token for `user`
+///
+/// # Arguments
+/// * `family_id` - The rotation chain the refresh token belongs to; a new chain is started when `None`
+/// * `auth` - The assertion the user just performed, if the tokens are minted by one
+async fn issue_tokens(
+    user: &User,
+    tokens: &dyn TokenRepository,
+    family_id: Option<String>,
+    auth: Option<AuthContext>,
+) -> WebResult<AuthenticationResponse> {
+    let user_unique_id = Uuid::parse_str(&user.user_id)
+        .map_err(|e| Error::InvalidInput(format!("Invalid user ID: {}", e)))?;
+
+    let token = encode_jwt(&user_unique_id, &user.roles, auth)
+        .map_err(|e| Error::Token(format!("Failed to generate token: {}", e)))?;
+
+    let refresh_token = generate_refresh_token();
+    let now = chrono::Utc::now();
+    tokens
+        .store_refresh_token(RefreshToken {
+            token_hash: hash_refresh_token(&refresh_token),
+            user_id: user.user_id.clone(),
+            family_id: family_id.unwrap_or_else(|| Uuid::new_v4().to_string()),
+            created_at: DateTime::from_millis(now.timestamp_millis()),
+            expires_at: DateTime::from_millis((now + refresh_token_ttl()).timestamp_millis()),
+            used: false,
+            revoked: false,
+        })
+        .await
+        .map_err(|e| Error::Database(e.to_string()))?;
+
+    Ok(AuthenticationResponse {
+        token,
+        refresh_token,
+    })
+}
+
+/// Patches the passkey used for `auth_result`; the counter is persisted when it moved
+async fn record_assertion(
+    user: &mut User,
+    auth_result: &AuthenticationResult,
+    db: &dyn UserRepository,
+) -> WebResult<()> {
+    let used_credential = auth_result.cred_id().to_string();
+    let updated_key = user
+        .keys
+        .iter_mut()
+        .find(|key| key.cred_id() == auth_result.cred_id())
+        .ok_or(Error::CredentialNotFound)?;
+    let changed = updated_key.update_credential(auth_result) == Some(true);
+
+    db.update_credential(
+        user.user_id.clone(),
+        used_credential,
+        changed.then(|| updated_key.clone()),
+        chrono::Utc::now(),
+    )
+    .await
+    .map_err(|e| Error::Database(e.to_string()))?;
+    Ok(())
+}
+
+/// Hands freshly issued tokens to the client according to the configured session mode
+///
+/// Bearer clients get them in the body. In cookie mode they are kept in the
+/// session cookie and only the CSRF token is returned.
+///
+/// # Arguments
+/// * `renew` - Whether this is a new sign-in, which gets a new session ID and CSRF token
+fn token_response(
+    req: &HttpRequest,
+    session: &Session,
+    response: AuthenticationResponse,
+    renew: bool,
+) -> WebResult<HttpResponse> {
+    let mode = req
+        .app_data::<Data<SessionMode>>()
+        .map_or(SessionMode::Bearer, |mode| *mode.get_ref());
+    if mode == SessionMode::Bearer {
+        return Ok(HttpResponse::Ok().json(response));
+    }
+
+    // Rotate the session ID on sign-in so a planted session cannot be taken over
+    if renew {
+        session.renew();
+    }
+    let csrf_token = match session.get::<String>(CSRF_TOKEN_KEY) {
+        Ok(Some(csrf_token)) if !renew => csrf_token,
+        _ => generate_csrf_token(),
+    };
+
+    session
+        .insert(ACCESS_TOKEN_KEY, response.token)
+        .and_then(|_| session.insert(REFRESH_TOKEN_KEY, response.refresh_token))
+        .and_then(|_| session.insert(CSRF_TOKEN_KEY, &csrf_token))
+        .map_err(|_| Error::CorruptSession)?;
+
+    Ok(HttpResponse::Ok()
+        .cookie(csrf_cookie(&csrf_token))
+        .json(CookieSessionResponse { csrf_token }))
+}
+
+/// Signs a cookie-mode client out by dropping its session and CSRF cookie
+pub(crate) fn end_session(session: &Session, response: &mut HttpResponse) {
+    session.purge();
+    let _ = response.add_removal_cookie(&removal_csrf_cookie());
+}
+
+/// The form a username given at sign-in is stored under; names that can no
+/// longer be registered are looked up as given
+fn lookup_username(raw: &str) -> String {
+    normalize_username(raw).unwrap_or_else(|_| raw.to_string())
+}
+
+/// Removes the ceremony ID stored under `key` from the session, if any
+fn take_ceremony_id(session: &Session, key: &str) -> Option<String> {
+    session
+        .remove_as::<String>(key)
+        .and_then(|ceremony_id| ceremony_id.ok())
 }
 
 pub mod registration {
     use super::*;
 
+    #[derive(Debug, Deserialize)]
+    pub(crate) struct RegistrationQuery {
+        display_name: Option<String>,
+    }
+
     #[post("start_reg/{username}")]
+    #[allow(clippy::too_many_arguments)]
     pub(crate) async fn start(
         username: Path<String>,
+        query: Query<RegistrationQuery>,
+        session: Session,
         reg_state_storage: Data<RegistrationState>,
+        db: Data<dyn UserRepository>,
         webauthn: Data<Webauthn>,
+        rp_config: Data<RelyingPartyConfig>,
     ) -> WebResult<Json<CreationChallengeResponse>> {
+        let username = normalize_username(&username.into_inner())
+            .map_err(|e| Error::InvalidInput(e.to_string()))?;
+        let display_name = match query.into_inner().display_name {
+            Some(display_name) => normalize_display_name(&display_name)
+                .map_err(|e| Error::InvalidInput(e.to_string()))?,
+            None => None,
+        };
         info!("Starting registration for user: {}", username);
 
-        let username = username.into_inner();
+        // Refuse taken names before the authenticator is involved
+        if db
+            .is_username_taken(username.clone())
+            .await
+            .map_err(|e| Error::Database(e.to_string()))?
+        {
+            return Err(Error::Conflict("Username taken".to_string()));
+        }
+
         let user_unique_id = Uuid::new_v4();
 
-        let (challenge_response, reg_state) = webauthn
-            .start_passkey_registration(user_unique_id, &username, &username, None)
+        let (mut challenge_response, reg_state) = webauthn
+            .start_passkey_registration(
+                user_unique_id,
+                &username,
+                display_name.as_deref().unwrap_or(&username),
+                None,
+            )
             .map_err(|e| {
                 error!("Failed to start registration: {:?}", e);
                 Error::Unknown(e)
             })?;
+        rp_config.apply_registration_policy(&mut challenge_response);
+
+        // Drop any ceremony this session started but never finished
+        if let Some(previous) = take_ceremony_id(&session, REG_CEREMONY_KEY) {
+            let _ = reg_state_storage.remove(&previous).await;
+        }
 
         info!("Storing registration state for user: {}", username);
-        reg_state_storage
-            .insert((username, user_unique_id, reg_state))
-            .await;
+        let ceremony_id = reg_state_storage
+            .insert(RegistrationData {
+                username,
+                display_name,
+                user_id: user_unique_id,
+                registration: reg_state,
+            })
+            .await
+            .map_err(ceremony_error)?;
+
+        session
+            .insert(REG_CEREMONY_KEY, ceremony_id)
+            .map_err(|_| Error::CorruptSession)?;
 
         Ok(Json(challenge_response))
     }
 
     #[post("finish_reg")]
     pub(crate) async fn finish(
+        http_req: HttpRequest,
         req: Json<RegisterPublicKeyCredential>,
+        session: Session,
         reg_state_storage: Data<RegistrationState>,
         db: Data<dyn UserRepository>,
         webauthn: Data<Webauthn>,
+        rp_config: Data<RelyingPartyConfig>,
     ) -> WebResult<HttpResponse> {
-        let registration_state = reg_state_storage
-            .get("reg_state".to_string())
-            .await
-            .map_err(|_| Error::CorruptSession)?;
+        let mut event = AuthEvent::new(AuthEventType::Registration, &http_req);
+        let result: WebResult<HttpResponse> = async {
+            let ceremony_id =
+                take_ceremony_id(&session, REG_CEREMONY_KEY).ok_or(Error::CeremonyNotFound)?;
 
-        let (username, user_unique_id, reg_state) = registration_state;
+            let RegistrationData {
+                username,
+                display_name,
+                user_id: user_unique_id,
+                registration: reg_state,
+            } = reg_state_storage
+                .take(&ceremony_id)
+                .await
+                .map_err(ceremony_error)?;
+            event.user_id = Some(user_unique_id.to_string());
+            event.username = Some(username.clone());
 
-        let passkey = webauthn
-            .finish_passkey_registration(&req, &reg_state)
-            .map_err(|e| {
-                error!("Failed to finish registration: {:?}", e);
-                Error::BadRequest(e)
-            })?;
+            let passkey = webauthn
+                .finish_passkey_registration(&req, &reg_state)
+                .map_err(|e| {
+                    error!("Failed to finish registration: {:?}", e);
+                    Error::BadRequest(e)
+                })?;
+            rp_config
+                .check_attestation(&passkey)
+                .map_err(|e| Error::Forbidden(e.to_string()))?;
+            event.credential_id = Some(passkey.cred_id().to_string());
 
-        let user = User {
-            user_id: user_unique_id.to_string(),
-            user_name: username.clone(),
-            keys: vec![passkey],
-            owned_polls: Some(Vec::new()),
-            polls_voted: Some(Vec::new()),
-        };
+            // The name may have been reserved by an account deletion since the ceremony started
+            if db
+                .is_username_taken(username.clone())
+                .await
+                .map_err(|e| Error::Database(e.to_string()))?
+            {
+                return Err(Error::Conflict("Username taken".to_string()));
+            }
 
-        db.create_user(user)
-            .await
-            .map_err(|e| Error::Database(e.to_string()))?;
+            let (recovery_codes, recovery_code_hashes) = generate_recovery_codes();
+
+            let user = User {
+                user_id: user_unique_id.to_string(),
+                user_name: username.clone(),
+                credentials: vec![CredentialInfo::new(&passkey, None)],
+                keys: vec![passkey],
+                owned_polls: Some(Vec::new()),
+                polls_voted: Some(Vec::new()),
+                roles: vec![Role::User],
+                recovery_codes: recovery_code_hashes,
+                profile: Profile {
+                    display_name,
+                    ..Profile::default()
+                },
+            };
+
+            if let Err(e) = db.create_user(user).await {
+                let e = e.to_string();
+                // Another registration for the same name finished first; the unique index rejected ours
+                return match db.get_user(username).await {
+                    Ok(Some(_)) => Err(Error::Conflict("Username taken".to_string())),
+                    _ => Err(Error::Database(e)),
+                };
+            }
 
-        info!("Successfully registered user: {}", username);
-        Ok(HttpResponse::Ok().json("Registration successful"))
+            info!("Successfully registered user: {}", username);
+            Ok(HttpResponse::Ok().json(RecoveryCodesResponse { recovery_codes }))
+        }
+        .await;
+
+        audit(&http_req, event.finish(&result)).await;
+        result
     }
 }
 
@@ -93,20 +414,50 @@ pub mod registration {
 pub mod authentication {
     use super::*;
 
+    /// Persists the outcome of a successful assertion and issues tokens for `user`
+    async fn complete_login(
+        mut user: User,
+        auth_result: &AuthenticationResult,
+        db: &dyn UserRepository,
+        tokens: &dyn TokenRepository,
+    ) -> WebResult<AuthenticationResponse> {
+        // Administrators must prove who they are on the authenticator, not just possess it
+        if user.roles.contains(&Role::Admin) && !auth_result.user_verified() {
+            return Err(Error::Forbidden(
+                "Administrators must sign in with user verification".to_string(),
+            ));
+        }
+
+        // Patch only the passkey that was used
+        record_assertion(&mut user, auth_result, db).await?;
+
+        info!("Authentication Successful!");
+
+        // Generate JWT and refresh tokens
+        let auth = AuthContext::now(auth_result.user_verified());
+        let response = issue_tokens(&user, tokens, None, Some(auth)).await?;
+
+        info!("Successfully authenticated user: {}", user.user_name);
+        Ok(response)
+    }
+
     #[post("start_auth/{username}")]
     pub(crate) async fn start(
         username: Path<String>,
+        session: Session,
         db: Data<dyn UserRepository>,
         auth_state_store: Data<AuthenticationState>,
         webauthn: Data<Webauthn>,
     ) -> WebResult<HttpResponse> {
         info!("Starting authentication for user: {}", username);
 
-        // Clean up any existing auth state
-        let _ = auth_state_store.remove("auth_state".to_string()).await;
+        // Clean up any auth state this session left behind
+        if let Some(previous) = take_ceremony_id(&session, AUTH_CEREMONY_KEY) {
+            let _ = auth_state_store.remove(&previous).await;
+        }
 
         let user = db
-            .get_user(username.to_string())
+            .get_user(lookup_username(&username))
             .await
             .map_err(|_| Error::CorruptSession)?
             .ok_or(Error::UserNotFound)?;
@@ -114,58 +465,983 @@ pub mod authentication {
         let user_unique_id = Uuid::parse_str(&user.user_id)
             .map_err(|e| Error::InvalidInput(format!("Invalid user ID: {}", e)))?;
 
-        let (challenge_response, auth_state) = webauthn
+        let (mut challenge_response, auth_state) = webauthn
             .start_passkey_authentication(&user.keys)
             .map_err(|e| {
                 error!("Failed to start authentication: {:?}", e);
                 Error::Unknown(e)
             })?;
+        if user.roles.contains(&Role::Admin) {
+            challenge_response.public_key.user_verification = UserVerificationPolicy::Required;
+        }
 
-        auth_state_store.insert((user_unique_id, auth_state)).await;
+        let ceremony_id = auth_state_store
+            .insert(AuthenticationData::Passkey {
+                user_id: user_unique_id,
+                authentication: auth_state,
+            })
+            .await
+            .map_err(ceremony_error)?;
+
+        session
+            .insert(AUTH_CEREMONY_KEY, ceremony_id)
+            .map_err(|_| Error::CorruptSession)?;
 
         Ok(HttpResponse::Ok().json(challenge_response))
     }
 
     #[post("/finish_auth/{username}")]
+    #[allow(clippy::too_many_arguments)]
     pub(crate) async fn finish(
+        http_req: HttpRequest,
         auth: Json<PublicKeyCredential>,
         username: Path<String>,
+        session: Session,
         auth_state_store: Data<AuthenticationState>,
         db: Data<dyn UserRepository>,
+        tokens: Data<dyn TokenRepository>,
+        webauthn: Data<Webauthn>,
+    ) -> WebResult<HttpResponse> {
+        let mut event = AuthEvent::new(AuthEventType::Login, &http_req);
+        event.username = Some(username.to_string());
+        event.credential_id = Some(auth.id.clone());
+
+        let result: WebResult<HttpResponse> = async {
+            let ceremony_id =
+                take_ceremony_id(&session, AUTH_CEREMONY_KEY).ok_or(Error::CeremonyNotFound)?;
+
+            let AuthenticationData::Passkey {
+                user_id: user_unique_id,
+                authentication: auth_state,
+            } = auth_state_store
+                .take(&ceremony_id)
+                .await
+                .map_err(ceremony_error)?
+            else {
+                return Err(Error::CeremonyNotFound);
+            };
+
+            event.user_id = Some(user_unique_id.to_string());
+
+            let auth_result = webauthn
+                .finish_passkey_authentication(&auth, &auth_state)
+                .map_err(|e| {
+                    if matches!(e, WebauthnError::CredentialPossibleCompromise) {
+                        event.event_type = AuthEventType::CounterRegression;
+                    }
+                    assertion_error(e, &user_unique_id.to_string(), &auth.id)
+                })?;
+
+            let user = db
+                .get_user(lookup_username(&username))
+                .await
+                .map_err(|_| Error::CorruptSession)?
+                .ok_or(Error::UserNotFound)?;
+
+            // The ceremony must belong to the user named in the path
+            if user.user_id != user_unique_id.to_string() {
+                return Err(Error::CeremonyNotFound);
+            }
+
+            let response = complete_login(user, &auth_result, db.as_ref(), tokens.as_ref()).await?;
+            token_response(&http_req, &session, response, true)
+        }
+        .await;
+
+        audit(&http_req, event.finish(&result)).await;
+        result
+    }
+
+    /// Starts a usernameless sign-in; the browser offers whichever resident passkey the user picks
+    #[post("start_discoverable_auth")]
+    pub(crate) async fn start_discoverable(
+        session: Session,
+        auth_state_store: Data<AuthenticationState>,
         webauthn: Data<Webauthn>,
     ) -> WebResult<HttpResponse> {
-        let auth_state = auth_state_store
-            .get("auth_state".to_string())
+        if let Some(previous) = take_ceremony_id(&session, AUTH_CEREMONY_KEY) {
+            let _ = auth_state_store.remove(&previous).await;
+        }
+
+        let (challenge_response, auth_state) =
+            webauthn.start_discoverable_authentication().map_err(|e| {
+                error!("Failed to start discoverable authentication: {:?}", e);
+                Error::Unknown(e)
+            })?;
+
+        let ceremony_id = auth_state_store
+            .insert(AuthenticationData::Discoverable {
+                authentication: auth_state,
+            })
             .await
+            .map_err(ceremony_error)?;
+
+        session
+            .insert(AUTH_CEREMONY_KEY, ceremony_id)
             .map_err(|_| Error::CorruptSession)?;
 
-        let (user_unique_id, auth_state) = auth_state;
+        Ok(HttpResponse::Ok().json(challenge_response))
+    }
+
+    /// Finishes a usernameless sign-in, identifying the user from the returned user handle
+    #[post("finish_discoverable_auth")]
+    pub(crate) async fn finish_discoverable(
+        http_req: HttpRequest,
+        auth: Json<PublicKeyCredential>,
+        session: Session,
+        auth_state_store: Data<AuthenticationState>,
+        db: Data<dyn UserRepository>,
+        tokens: Data<dyn TokenRepository>,
+        webauthn: Data<Webauthn>,
+    ) -> WebResult<HttpResponse> {
+        let mut event = AuthEvent::new(AuthEventType::Login, &http_req);
+        event.credential_id = Some(auth.id.clone());
+
+        let result: WebResult<HttpResponse> = async {
+            let ceremony_id =
+                take_ceremony_id(&session, AUTH_CEREMONY_KEY).ok_or(Error::CeremonyNotFound)?;
+
+            let AuthenticationData::Discoverable {
+                authentication: auth_state,
+            } = auth_state_store
+                .take(&ceremony_id)
+                .await
+                .map_err(ceremony_error)?
+            else {
+                return Err(Error::CeremonyNotFound);
+            };
+
+            let (user_unique_id, credential_id) = webauthn
+                .identify_discoverable_authentication(&auth)
+                .map_err(Error::BadRequest)?;
+            let credential_id = CredentialID::from(credential_id.to_vec()).to_string();
+
+            let user = db
+                .get_user_by_credential(credential_id)
+                .await
+                .map_err(|e| Error::Database(e.to_string()))?
+                .ok_or(Error::UserNotFound)?;
+
+            // The user handle must agree with the owner of the credential
+            if user.user_id != user_unique_id.to_string() {
+                return Err(Error::UserNotFound);
+            }
+            event.user_id = Some(user.user_id.clone());
+            event.username = Some(user.user_name.clone());
+
+            let discoverable_keys: Vec<DiscoverableKey> =
+                user.keys.iter().map(DiscoverableKey::from).collect();
 
-        let auth_result = webauthn
-            .finish_passkey_authentication(&auth, &auth_state)
-            .map_err(|e| Error::BadRequest(e))?;
+            let auth_result = webauthn
+                .finish_discoverable_authentication(&auth, auth_state, &discoverable_keys)
+                .map_err(|e| {
+                    if matches!(e, WebauthnError::CredentialPossibleCompromise) {
+                        event.event_type = AuthEventType::CounterRegression;
+                    }
+                    assertion_error(e, &user.user_id, &auth.id)
+                })?;
 
-        let mut user = db
-            .get_user(username.into_inner())
+            let response = complete_login(user, &auth_result, db.as_ref(), tokens.as_ref()).await?;
+            token_response(&http_req, &session, response, true)
+        }
+        .await;
+
+        audit(&http_req, event.finish(&result)).await;
+        result
+    }
+}
+
+/// Refresh token rotation and logout endpoints
+pub mod tokens {
+    use super::*;
+
+    /// Rotates the refresh token given in the body, or the one held by a cookie-mode session
+    #[post("refresh")]
+    pub(crate) async fn refresh(
+        http_req: HttpRequest,
+        req: Option<Json<RefreshRequest>>,
+        session: Session,
+        db: Data<dyn UserRepository>,
+        tokens: Data<dyn TokenRepository>,
+    ) -> WebResult<HttpResponse> {
+        let presented = match req {
+            Some(req) => req.into_inner().refresh_token,
+            None => {
+                let refresh_token = session
+                    .get::<String>(REFRESH_TOKEN_KEY)
+                    .map_err(|_| Error::CorruptSession)?
+                    .ok_or(Error::Unauthorized)?;
+                if !verify_csrf(&http_req, &session) {
+                    return Err(Error::Forbidden(
+                        "Missing or invalid CSRF token".to_string(),
+                    ));
+                }
+                refresh_token
+            }
+        };
+        let token_hash = hash_refresh_token(&presented);
+
+        let consumed = tokens
+            .consume_refresh_token(&token_hash)
             .await
-            .map_err(|_| Error::CorruptSession)?
-            .ok_or(Error::UserNotFound)?;
+            .map_err(|e| Error::Database(e.to_string()))?;
 
-        // Update user credentials
-        user.keys.iter_mut().for_each(|key| {
-            key.update_credential(&auth_result);
-        });
+        let Some(refresh_token) = consumed else {
+            // A used or revoked token being presented again means it leaked:
+            // kill every token descended from the same login.
+            if let Some(replayed) = tokens
+                .find_refresh_token(&token_hash)
+                .await
+                .map_err(|e| Error::Database(e.to_string()))?
+            {
+                if replayed.used {
+                    warn!(
+                        "Refresh token reuse detected for user {}, revoking its family",
+                        replayed.user_id
+                    );
+                    tokens
+                        .revoke_refresh_family(&replayed.family_id)
+                        .await
+                        .map_err(|e| Error::Database(e.to_string()))?;
+                }
+            }
+            return Err(Error::Unauthorized);
+        };
 
-        db.delete_user(user_unique_id.to_string()).await.unwrap();
-        db.create_user(user.clone()).await.unwrap();
+        let user = db
+            .get_user_by_id(refresh_token.user_id)
+            .await
+            .map_err(|e| Error::Database(e.to_string()))?
+            .ok_or(Error::Unauthorized)?;
 
-        info!("Authentication Successful!");
+        let response =
+            issue_tokens(&user, tokens.as_ref(), Some(refresh_token.family_id), None).await?;
+        token_response(&http_req, &session, response, false)
+    }
 
-        // Generate JWT token
-        let token = encode_jwt(&user_unique_id)
-            .map_err(|e| Error::Token(format!("Failed to generate token: {}", e)))?;
+    /// Revokes the presented access token and, if given, the refresh token's family
+    #[post("logout", wrap = "CheckAuth")]
+    pub(crate) async fn logout(
+        claims: Claims,
+        req: Option<Json<LogoutRequest>>,
+        session: Session,
+        tokens: Data<dyn TokenRepository>,
+    ) -> WebResult<HttpResponse> {
+        tokens
+            .revoke_access_token(&claims)
+            .await
+            .map_err(|e| Error::Database(e.to_string()))?;
 
-        info!("Successfully authenticated user: {}", user.user_name);
-        Ok(HttpResponse::Ok().json(AuthenticationResponse { token }))
+        let refresh_token = req
+            .and_then(|req| req.into_inner().refresh_token)
+            .or_else(|| session.get::<String>(REFRESH_TOKEN_KEY).ok().flatten());
+        if let Some(refresh_token) = refresh_token {
+            let stored = tokens
+                .find_refresh_token(&hash_refresh_token(&refresh_token))
+                .await
+                .map_err(|e| Error::Database(e.to_string()))?;
+
+            // Only the caller's own refresh tokens can be revoked this way
+            if let Some(stored) = stored.filter(|t| t.user_id == claims.uuid.to_string()) {
+                tokens
+                    .revoke_refresh_family(&stored.family_id)
+                    .await
+                    .map_err(|e| Error::Database(e.to_string()))?;
+            }
+        }
+
+        let mut response = HttpResponse::Ok().json("Logged out");
+        end_session(&session, &mut response);
+        Ok(response)
+    }
+
+    /// Revokes every refresh token and every access token issued to the caller so far
+    #[post("logout_all", wrap = "CheckAuth")]
+    pub(crate) async fn logout_all(
+        claims: Claims,
+        session: Session,
+        tokens: Data<dyn TokenRepository>,
+    ) -> WebResult<HttpResponse> {
+        let user_id = claims.uuid.to_string();
+
+        tokens
+            .revoke_user_refresh_tokens(&user_id)
+            .await
+            .map_err(|e| Error::Database(e.to_string()))?;
+        tokens
+            .revoke_all_access_tokens(&user_id)
+            .await
+            .map_err(|e| Error::Database(e.to_string()))?;
+
+        info!("Logged out user {} everywhere", user_id);
+        let mut response = HttpResponse::Ok().json("Logged out everywhere");
+        end_session(&session, &mut response);
+        Ok(response)
+    }
+}
+
+/// Passkey management for signed-in users
+pub mod passkeys {
+    use super::*;
+
+    #[derive(Debug, Deserialize)]
+    pub(crate) struct AddPasskeyQuery {
+        nickname: Option<String>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub(crate) struct RenamePasskeyRequest {
+        nickname: String,
+    }
+
+    /// Loads the user behind the access token
+    async fn current_user(db: &dyn UserRepository, claims: &Claims) -> WebResult<User> {
+        db.get_user_by_id(claims.uuid.to_string())
+            .await
+            .map_err(|e| Error::Database(e.to_string()))?
+            .ok_or(Error::Unauthorized)
+    }
+
+    /// Starts a ceremony that adds another authenticator to the caller's account
+    #[post("passkeys/start", wrap = "CheckAuth")]
+    pub(crate) async fn start(
+        RecentAuth(claims): RecentAuth,
+        session: Session,
+        db: Data<dyn UserRepository>,
+        reg_state_storage: Data<RegistrationState>,
+        webauthn: Data<Webauthn>,
+        rp_config: Data<RelyingPartyConfig>,
+    ) -> WebResult<Json<CreationChallengeResponse>> {
+        let user = current_user(db.as_ref(), &claims).await?;
+
+        // Stop the browser from registering an authenticator that is already enrolled
+        let exclude_credentials = user.keys.iter().map(|key| key.cred_id().clone()).collect();
+
+        let (mut challenge_response, reg_state) = webauthn
+            .start_passkey_registration(
+                claims.uuid,
+                &user.user_name,
+                user.display_name(),
+                Some(exclude_credentials),
+            )
+            .map_err(|e| {
+                error!("Failed to start passkey enrollment: {:?}", e);
+                Error::Unknown(e)
+            })?;
+        rp_config.apply_registration_policy(&mut challenge_response);
+
+        if let Some(previous) = take_ceremony_id(&session, ADD_PASSKEY_CEREMONY_KEY) {
+            let _ = reg_state_storage.remove(&previous).await;
+        }
+
+        let ceremony_id = reg_state_storage
+            .insert(RegistrationData {
+                username: user.user_name,
+                display_name: None,
+                user_id: claims.uuid,
+                registration: reg_state,
+            })
+            .await
+            .map_err(ceremony_error)?;
+
+        session
+            .insert(ADD_PASSKEY_CEREMONY_KEY, ceremony_id)
+            .map_err(|_| Error::CorruptSession)?;
+
+        Ok(Json(challenge_response))
+    }
+
+    #[post("passkeys/finish", wrap = "CheckAuth")]
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) async fn finish(
+        claims: Claims,
+        req: Json<RegisterPublicKeyCredential>,
+        query: Query<AddPasskeyQuery>,
+        session: Session,
+        db: Data<dyn UserRepository>,
+        reg_state_storage: Data<RegistrationState>,
+        webauthn: Data<Webauthn>,
+        rp_config: Data<RelyingPartyConfig>,
+    ) -> WebResult<HttpResponse> {
+        let ceremony_id =
+            take_ceremony_id(&session, ADD_PASSKEY_CEREMONY_KEY).ok_or(Error::CeremonyNotFound)?;
+
+        let RegistrationData {
+            user_id,
+            registration: reg_state,
+            ..
+        } = reg_state_storage
+            .take(&ceremony_id)
+            .await
+            .map_err(ceremony_error)?;
+
+        // The ceremony must have been started by the same account
+        if user_id != claims.uuid {
+            return Err(Error::CeremonyNotFound);
+        }
+
+        let passkey = webauthn
+            .finish_passkey_registration(&req, &reg_state)
+            .map_err(|e| {
+                error!("Failed to finish passkey enrollment: {:?}", e);
+                Error::BadRequest(e)
+            })?;
+        rp_config
+            .check_attestation(&passkey)
+            .map_err(|e| Error::Forbidden(e.to_string()))?;
+
+        let info = CredentialInfo::new(&passkey, query.into_inner().nickname);
+        db.add_passkey(user_id.to_string(), passkey, info.clone())
+            .await
+            .map_err(|e| Error::Database(e.to_string()))?;
+
+        info!("Added passkey {} for user {}", info.credential_id, user_id);
+        Ok(HttpResponse::Ok().json(info))
+    }
+
+    #[get("passkeys", wrap = "CheckAuth")]
+    pub(crate) async fn list(
+        claims: Claims,
+        db: Data<dyn UserRepository>,
+    ) -> WebResult<Json<Vec<CredentialInfo>>> {
+        let user = current_user(db.as_ref(), &claims).await?;
+        Ok(Json(user.credential_infos()))
+    }
+
+    #[patch("passkeys/{credential_id}", wrap = "CheckAuth")]
+    pub(crate) async fn rename(
+        claims: Claims,
+        credential_id: Path<String>,
+        req: Json<RenamePasskeyRequest>,
+        db: Data<dyn UserRepository>,
+    ) -> WebResult<HttpResponse> {
+        let nickname = req.into_inner().nickname.trim().to_string();
+        if nickname.is_empty() || nickname.chars().count() > 64 {
+            return Err(Error::InvalidInput(
+                "Nickname must be between 1 and 64 characters".to_string(),
+            ));
+        }
+
+        let renamed = db
+            .rename_passkey(
+                claims.uuid.to_string(),
+                credential_id.into_inner(),
+                nickname,
+            )
+            .await
+            .map_err(|e| Error::Database(e.to_string()))?;
+
+        if !renamed {
+            return Err(Error::CredentialNotFound);
+        }
+
+        Ok(HttpResponse::Ok().json("Passkey renamed"))
+    }
+
+    #[delete("passkeys/{credential_id}", wrap = "CheckAuth")]
+    pub(crate) async fn revoke(
+        RecentAuth(claims): RecentAuth,
+        credential_id: Path<String>,
+        db: Data<dyn UserRepository>,
+    ) -> WebResult<HttpResponse> {
+        let credential_id = credential_id.into_inner();
+        let user = current_user(db.as_ref(), &claims).await?;
+
+        if !user
+            .keys
+            .iter()
+            .any(|key| key.cred_id().to_string() == credential_id)
+        {
+            return Err(Error::CredentialNotFound);
+        }
+
+        let removed = db
+            .remove_passkey(user.user_id.clone(), credential_id.clone())
+            .await
+            .map_err(|e| Error::Database(e.to_string()))?;
+
+        if !removed {
+            return Err(Error::Conflict(
+                "The last passkey on an account cannot be removed".to_string(),
+            ));
+        }
+
+        info!(
+            "Revoked passkey {} for user {}",
+            credential_id, user.user_id
+        );
+        Ok(HttpResponse::Ok().json("Passkey revoked"))
+    }
+}
+
+/// Step-up re-authentication, required shortly before destructive operations
+pub mod step_up {
+    use super::*;
+
+    /// Challenges the signed-in user to verify themselves on one of their passkeys
+    #[post("step_up/start", wrap = "CheckAuth")]
+    pub(crate) async fn start(
+        claims: Claims,
+        session: Session,
+        db: Data<dyn UserRepository>,
+        auth_state_store: Data<AuthenticationState>,
+        webauthn: Data<Webauthn>,
+    ) -> WebResult<HttpResponse> {
+        if let Some(previous) = take_ceremony_id(&session, STEP_UP_CEREMONY_KEY) {
+            let _ = auth_state_store.remove(&previous).await;
+        }
+
+        let user = db
+            .get_user_by_id(claims.uuid.to_string())
+            .await
+            .map_err(|e| Error::Database(e.to_string()))?
+            .ok_or(Error::Unauthorized)?;
+
+        let (mut challenge_response, auth_state) = webauthn
+            .start_passkey_authentication(&user.keys)
+            .map_err(|e| {
+                error!("Failed to start step-up: {:?}", e);
+                Error::Unknown(e)
+            })?;
+        // Possessing the authenticator is not enough; the user must unlock it
+        challenge_response.public_key.user_verification = UserVerificationPolicy::Required;
+
+        let ceremony_id = auth_state_store
+            .insert(AuthenticationData::Passkey {
+                user_id: claims.uuid,
+                authentication: auth_state,
+            })
+            .await
+            .map_err(ceremony_error)?;
+
+        session
+            .insert(STEP_UP_CEREMONY_KEY, ceremony_id)
+            .map_err(|_| Error::CorruptSession)?;
+
+        Ok(HttpResponse::Ok().json(challenge_response))
+    }
+
+    /// Verifies the assertion and issues an elevated token valid for `step_up_max_age`
+    #[post("step_up/finish", wrap = "CheckAuth")]
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) async fn finish(
+        http_req: HttpRequest,
+        claims: Claims,
+        auth: Json<PublicKeyCredential>,
+        session: Session,
+        auth_state_store: Data<AuthenticationState>,
+        db: Data<dyn UserRepository>,
+        webauthn: Data<Webauthn>,
+    ) -> WebResult<HttpResponse> {
+        let mut event = AuthEvent::new(AuthEventType::StepUp, &http_req);
+        event.user_id = Some(claims.uuid.to_string());
+        event.credential_id = Some(auth.id.clone());
+
+        let result: WebResult<HttpResponse> = async {
+            let ceremony_id =
+                take_ceremony_id(&session, STEP_UP_CEREMONY_KEY).ok_or(Error::CeremonyNotFound)?;
+
+            let AuthenticationData::Passkey {
+                user_id,
+                authentication: auth_state,
+            } = auth_state_store
+                .take(&ceremony_id)
+                .await
+                .map_err(ceremony_error)?
+            else {
+                return Err(Error::CeremonyNotFound);
+            };
+
+            // The ceremony must have been started by the same user
+            if user_id != claims.uuid {
+                return Err(Error::CeremonyNotFound);
+            }
+
+            let auth_result = webauthn
+                .finish_passkey_authentication(&auth, &auth_state)
+                .map_err(|e| {
+                    if matches!(e, WebauthnError::CredentialPossibleCompromise) {
+                        event.event_type = AuthEventType::CounterRegression;
+                    }
+                    assertion_error(e, &user_id.to_string(), &auth.id)
+                })?;
+            if !auth_result.user_verified() {
+                return Err(Error::Forbidden(
+                    "The authenticator did not verify the user".to_string(),
+                ));
+            }
+
+            let mut user = db
+                .get_user_by_id(user_id.to_string())
+                .await
+                .map_err(|e| Error::Database(e.to_string()))?
+                .ok_or(Error::Unauthorized)?;
+            event.username = Some(user.user_name.clone());
+            record_assertion(&mut user, &auth_result, db.as_ref()).await?;
+
+            let token = encode_step_up_jwt(&user_id, &user.roles, AuthContext::now(true))
+                .map_err(|e| Error::Token(format!("Failed to generate token: {}", e)))?;
+            let expires_in = step_up_max_age().num_seconds();
+            info!("User {} stepped up", user.user_name);
+
+            // Cookie-mode sessions carry the elevated token until it expires and is refreshed
+            if session
+                .get::<String>(ACCESS_TOKEN_KEY)
+                .map_err(|_| Error::CorruptSession)?
+                .is_some()
+            {
+                session
+                    .insert(ACCESS_TOKEN_KEY, token)
+                    .map_err(|_| Error::CorruptSession)?;
+                return Ok(HttpResponse::Ok().json(StepUpResponse {
+                    token: None,
+                    expires_in,
+                }));
+            }
+
+            Ok(HttpResponse::Ok().json(StepUpResponse {
+                token: Some(token),
+                expires_in,
+            }))
+        }
+        .await;
+
+        audit(&http_req, event.finish(&result)).await;
+        result
+    }
+}
+
+/// Recovery codes, the fallback for users who have lost every passkey
+pub mod recovery {
+    use super::*;
+
+    #[derive(Debug, Deserialize)]
+    pub(crate) struct RecoveryPasskeyQuery {
+        nickname: Option<String>,
+    }
+
+    /// Replaces the caller's recovery codes, invalidating the old ones
+    #[post("recovery_codes", wrap = "CheckAuth")]
+    pub(crate) async fn regenerate(
+        RecentAuth(claims): RecentAuth,
+        db: Data<dyn UserRepository>,
+    ) -> WebResult<Json<RecoveryCodesResponse>> {
+        let user_id = claims.uuid.to_string();
+        let (recovery_codes, recovery_code_hashes) = generate_recovery_codes();
+
+        db.set_recovery_codes(user_id.clone(), recovery_code_hashes)
+            .await
+            .map_err(|e| Error::Database(e.to_string()))?;
+
+        security_event::report(&SecurityEvent::RecoveryCodesRegenerated { user_id });
+        Ok(Json(RecoveryCodesResponse { recovery_codes }))
+    }
+
+    /// Redeems a recovery code and starts enrolling a new passkey on the account
+    ///
+    /// The code is spent even if the enrollment is never finished.
+    #[post("recovery/start")]
+    pub(crate) async fn start(
+        http_req: HttpRequest,
+        req: Json<RecoveryRequest>,
+        session: Session,
+        db: Data<dyn UserRepository>,
+        reg_state_storage: Data<RegistrationState>,
+        webauthn: Data<Webauthn>,
+        rp_config: Data<RelyingPartyConfig>,
+    ) -> WebResult<Json<CreationChallengeResponse>> {
+        let mut event = AuthEvent::new(AuthEventType::Recovery, &http_req);
+        let result: WebResult<Json<CreationChallengeResponse>> = async {
+            let RecoveryRequest { username, code } = req.into_inner();
+            event.username = Some(username.clone());
+
+            let user = db
+                .get_user(lookup_username(&username))
+                .await
+                .map_err(|e| Error::Database(e.to_string()))?;
+
+            // Unknown users and wrong codes look the same to the caller
+            let remaining = match &user {
+                Some(user) => db
+                    .consume_recovery_code(user.user_id.clone(), hash_recovery_code(&code))
+                    .await
+                    .map_err(|e| Error::Database(e.to_string()))?,
+                None => None,
+            };
+            if let Some(user) = &user {
+                event.user_id = Some(user.user_id.clone());
+            }
+            let (Some(user), Some(remaining)) = (user, remaining) else {
+                security_event::report(&SecurityEvent::RecoveryCodeRejected { username });
+                return Err(Error::Unauthorized);
+            };
+
+            security_event::report(&SecurityEvent::RecoveryCodeUsed {
+                user_id: user.user_id.clone(),
+                remaining,
+            });
+
+            let user_unique_id = Uuid::parse_str(&user.user_id)
+                .map_err(|e| Error::InvalidInput(format!("Invalid user ID: {}", e)))?;
+
+            let (mut challenge_response, reg_state) = webauthn
+                .start_passkey_registration(
+                    user_unique_id,
+                    &user.user_name,
+                    user.display_name(),
+                    None,
+                )
+                .map_err(|e| {
+                    error!("Failed to start recovery enrollment: {:?}", e);
+                    Error::Unknown(e)
+                })?;
+            rp_config.apply_registration_policy(&mut challenge_response);
+
+            if let Some(previous) = take_ceremony_id(&session, RECOVERY_CEREMONY_KEY) {
+                let _ = reg_state_storage.remove(&previous).await;
+            }
+
+            let ceremony_id = reg_state_storage
+                .insert(RegistrationData {
+                    username: user.user_name,
+                    display_name: None,
+                    user_id: user_unique_id,
+                    registration: reg_state,
+                })
+                .await
+                .map_err(ceremony_error)?;
+
+            session
+                .insert(RECOVERY_CEREMONY_KEY, ceremony_id)
+                .map_err(|_| Error::CorruptSession)?;
+
+            Ok(Json(challenge_response))
+        }
+        .await;
+
+        audit(&http_req, event.finish(&result)).await;
+        result
     }
+
+    /// Enrolls the new passkey, signs out every existing session and signs the user in
+    ///
+    /// Administrators are not signed in, as enrollment does not prove user
+    /// verification; they sign in with the new passkey like any other login.
+    #[post("recovery/finish")]
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) async fn finish(
+        http_req: HttpRequest,
+        req: Json<RegisterPublicKeyCredential>,
+        query: Query<RecoveryPasskeyQuery>,
+        session: Session,
+        db: Data<dyn UserRepository>,
+        tokens: Data<dyn TokenRepository>,
+        reg_state_storage: Data<RegistrationState>,
+        webauthn: Data<Webauthn>,
+        rp_config: Data<RelyingPartyConfig>,
+    ) -> WebResult<HttpResponse> {
+        let mut event = AuthEvent::new(AuthEventType::Recovery, &http_req);
+        let result: WebResult<HttpResponse> = async {
+            let ceremony_id =
+                take_ceremony_id(&session, RECOVERY_CEREMONY_KEY).ok_or(Error::CeremonyNotFound)?;
+
+            let RegistrationData {
+                username,
+                user_id,
+                registration: reg_state,
+                ..
+            } = reg_state_storage
+                .take(&ceremony_id)
+                .await
+                .map_err(ceremony_error)?;
+            event.user_id = Some(user_id.to_string());
+            event.username = Some(username);
+
+            let passkey = webauthn
+                .finish_passkey_registration(&req, &reg_state)
+                .map_err(|e| {
+                    error!("Failed to finish recovery enrollment: {:?}", e);
+                    Error::BadRequest(e)
+                })?;
+            rp_config
+                .check_attestation(&passkey)
+                .map_err(|e| Error::Forbidden(e.to_string()))?;
+            event.credential_id = Some(passkey.cred_id().to_string());
+
+            let user_id = user_id.to_string();
+            let info = CredentialInfo::new(&passkey, query.into_inner().nickname);
+            db.add_passkey(user_id.clone(), passkey, info.clone())
+                .await
+                .map_err(|e| Error::Database(e.to_string()))?;
+
+            // Whoever holds the lost devices must not stay signed in
+            tokens
+                .revoke_user_refresh_tokens(&user_id)
+                .await
+                .map_err(|e| Error::Database(e.to_string()))?;
+            tokens
+                .revoke_all_access_tokens(&user_id)
+                .await
+                .map_err(|e| Error::Database(e.to_string()))?;
+            tokens
+                .revoke_user_api_tokens(&user_id)
+                .await
+                .map_err(|e| Error::Database(e.to_string()))?;
+
+            let user = db
+                .get_user_by_id(user_id)
+                .await
+                .map_err(|e| Error::Database(e.to_string()))?
+                .ok_or(Error::UserNotFound)?;
+
+            info!(
+                "Recovered account {} with passkey {}",
+                user.user_id, info.credential_id
+            );
+            if user.roles.contains(&Role::Admin) {
+                return Ok(HttpResponse::Ok()
+                    .json("Passkey enrolled; sign in with it using user verification"));
+            }
+
+            let response = issue_tokens(&user, tokens.as_ref(), None, None).await?;
+            token_response(&http_req, &session, response, true)
+        }
+        .await;
+
+        audit(&http_req, event.finish(&result)).await;
+        result
+    }
+}
+
+/// Personal access tokens for scripts that cannot perform a WebAuthn ceremony
+pub mod api_tokens {
+    use super::*;
+
+    /// Mints a named token limited to the requested scopes
+    #[post("api_tokens", wrap = "CheckAuth")]
+    pub(crate) async fn create(
+        RecentAuth(claims): RecentAuth,
+        req: Json<CreateApiTokenRequest>,
+        tokens: Data<dyn TokenRepository>,
+    ) -> WebResult<Json<CreatedApiToken>> {
+        let CreateApiTokenRequest {
+            name,
+            scopes,
+            expires_in_days,
+        } = req.into_inner();
+
+        let name = name.trim().to_string();
+        if name.is_empty() || name.chars().count() > 64 {
+            return Err(Error::InvalidInput(
+                "Name must be between 1 and 64 characters".to_string(),
+            ));
+        }
+        if scopes.is_empty() {
+            return Err(Error::InvalidInput(
+                "At least one scope is required".to_string(),
+            ));
+        }
+        let ttl_days = expires_in_days.unwrap_or(DEFAULT_API_TOKEN_TTL_DAYS);
+        if !(1..=MAX_API_TOKEN_TTL_DAYS).contains(&ttl_days) {
+            return Err(Error::InvalidInput(format!(
+                "expires_in_days must be between 1 and {}",
+                MAX_API_TOKEN_TTL_DAYS
+            )));
+        }
+
+        let token = generate_api_token();
+        let now = chrono::Utc::now();
+        let api_token = ApiToken {
+            token_id: Uuid::new_v4().to_string(),
+            token_hash: hash_api_token(&token),
+            user_id: claims.uuid.to_string(),
+            name,
+            scopes,
+            created_at: DateTime::from_millis(now.timestamp_millis()),
+            expires_at: DateTime::from_millis(
+                (now + chrono::Duration::days(ttl_days.into())).timestamp_millis(),
+            ),
+            last_used_at: None,
+        };
+
+        tokens
+            .store_api_token(api_token.clone())
+            .await
+            .map_err(|e| Error::Database(e.to_string()))?;
+
+        info!(
+            "Created API token {} for user {}",
+            api_token.token_id, api_token.user_id
+        );
+        Ok(Json(CreatedApiToken {
+            token,
+            info: ApiTokenInfo::from(&api_token),
+        }))
+    }
+
+    #[get("api_tokens", wrap = "CheckAuth")]
+    pub(crate) async fn list(
+        claims: Claims,
+        tokens: Data<dyn TokenRepository>,
+    ) -> WebResult<Json<Vec<ApiTokenInfo>>> {
+        let api_tokens = tokens
+            .list_api_tokens(&claims.uuid.to_string())
+            .await
+            .map_err(|e| Error::Database(e.to_string()))?;
+
+        Ok(Json(api_tokens.iter().map(ApiTokenInfo::from).collect()))
+    }
+
+    #[delete("api_tokens/{token_id}", wrap = "CheckAuth")]
+    pub(crate) async fn revoke(
+        claims: Claims,
+        token_id: Path<String>,
+        tokens: Data<dyn TokenRepository>,
+    ) -> WebResult<HttpResponse> {
+        let user_id = claims.uuid.to_string();
+        let token_id = token_id.into_inner();
+
+        let revoked = tokens
+            .revoke_api_token(&user_id, &token_id)
+            .await
+            .map_err(|e| Error::Database(e.to_string()))?;
+
+        if !revoked {
+            return Err(Error::ApiTokenNotFound);
+        }
+
+        info!("Revoked API token {} for user {}", token_id, user_id);
+        Ok(HttpResponse::Ok().json("API token revoked"))
+    }
+}
+
+/// Counters for in-flight and abandoned (swept) ceremonies; admins only
+#[get("ceremony_stats", wrap = "CheckAuth")]
+pub(crate) async fn ceremony_stats(
+    claims: Claims,
+    reg_state_storage: Data<RegistrationState>,
+    auth_state_store: Data<AuthenticationState>,
+) -> WebResult<Json<CeremonyStatsResponse>> {
+    if !claims.has_role(Role::Admin) {
+        return Err(Error::Forbidden(
+            "Only admins can read ceremony statistics".to_string(),
+        ));
+    }
+
+    Ok(Json(CeremonyStatsResponse {
+        registration: CeremonyCounters {
+            pending: reg_state_storage.pending().await.map_err(ceremony_error)?,
+            swept: reg_state_storage.swept_count(),
+        },
+        authentication: CeremonyCounters {
+            pending: auth_state_store.pending().await.map_err(ceremony_error)?,
+            swept: auth_state_store.swept_count(),
+        },
+    }))
+}
+
+/// Public keys other services can use to verify our access tokens
+#[get("/.well-known/jwks.json")]
+pub(crate) async fn jwks() -> Json<JwkSet> {
+    Json(keys().jwks.clone())
 }
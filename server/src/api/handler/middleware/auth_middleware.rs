@@ -1,18 +1,48 @@
-use actix_web::error::ErrorUnauthorized;
+use actix_session::SessionExt;
+use actix_web::error::{ErrorForbidden, ErrorInternalServerError, ErrorUnauthorized};
 use actix_web::{
     body::MessageBody,
     dev::{Service, ServiceRequest, ServiceResponse, Transform},
     http::header::AUTHORIZATION,
+    web::Data,
     Error, HttpMessage,
 };
 use futures::future::{ok, LocalBoxFuture, Ready};
 use std::rc::Rc;
 
-use crate::models::auth_jwt::decode_jwt;
+use crate::db::token_repository::TokenRepository;
+use crate::models::api_token_models::{hash_api_token, ApiToken, Scope, API_TOKEN_PREFIX};
+use crate::models::auth_jwt::{decode_jwt, Claims};
+use crate::models::session_mode::{verify_csrf, SessionMode, ACCESS_TOKEN_KEY};
 
-// Middleware struct
+// Middleware struct; accepts session JWTs only, as a bearer token or from the session cookie
 pub struct CheckAuth;
 
+impl CheckAuth {
+    /// Also accepts personal access tokens that were granted `scope`
+    pub fn scoped(scope: Scope) -> ScopedCheckAuth {
+        ScopedCheckAuth {
+            scope,
+            optional: false,
+        }
+    }
+
+    /// For public routes: lets anonymous requests through, but checks any
+    /// credentials presented, personal access tokens needing `scope`
+    pub fn optional(scope: Scope) -> ScopedCheckAuth {
+        ScopedCheckAuth {
+            scope,
+            optional: true,
+        }
+    }
+}
+
+// Middleware struct for routes that personal access tokens may call
+pub struct ScopedCheckAuth {
+    scope: Scope,
+    optional: bool,
+}
+
 // Implement `Transform` trait for `CheckAuth`
 impl<S, B> Transform<S, ServiceRequest> for CheckAuth
 where
@@ -29,6 +59,30 @@ where
     fn new_transform(&self, service: S) -> Self::Future {
         ok(CheckAuthMiddleware {
             service: Rc::new(service),
+            scope: None,
+            optional: false,
+        })
+    }
+}
+
+// Implement `Transform` trait for `ScopedCheckAuth`
+impl<S, B> Transform<S, ServiceRequest> for ScopedCheckAuth
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = CheckAuthMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(CheckAuthMiddleware {
+            service: Rc::new(service),
+            scope: Some(self.scope),
+            optional: self.optional,
         })
     }
 }
@@ -36,6 +90,39 @@ where
 // Inner middleware struct to hold the service
 pub struct CheckAuthMiddleware<S> {
     service: Rc<S>,
+    // Scope a personal access token needs here; `None` rejects them outright
+    scope: Option<Scope>,
+    // Whether requests without any credentials go through unauthenticated
+    optional: bool,
+}
+
+// Claims standing in for a personal access token; they never carry a role
+fn api_token_claims(token: &ApiToken) -> Result<Claims, Error> {
+    Ok(Claims {
+        exp: (token.expires_at.timestamp_millis() / 1000) as usize,
+        iat: (token.created_at.timestamp_millis() / 1000) as usize,
+        uuid: token
+            .user_id
+            .parse()
+            .map_err(|_| ErrorInternalServerError("Invalid user ID"))?,
+        jti: format!("{}{}", API_TOKEN_PREFIX, token.token_id),
+        roles: Vec::new(),
+        auth_time: None,
+        amr: Vec::new(),
+    })
+}
+
+// Rejects access tokens revoked by logout before their expiry
+async fn check_not_revoked(
+    tokens: Option<Data<dyn TokenRepository>>,
+    claims: &Claims,
+) -> Result<(), Error> {
+    let tokens = tokens.ok_or_else(|| ErrorInternalServerError("Token store unavailable"))?;
+    match tokens.is_access_token_revoked(claims).await {
+        Ok(false) => Ok(()),
+        Ok(true) => Err(ErrorUnauthorized("Token revoked")),
+        Err(e) => Err(ErrorInternalServerError(e.to_string())),
+    }
 }
 
 // Implement `Service` trait for `CheckAuthMiddleware`
@@ -60,16 +147,48 @@ where
         // Get the Authorization header
         let auth_header = req.headers().get(AUTHORIZATION).cloned();
 
+        // Revocation lookups go through the shared token repository
+        let tokens = req.app_data::<Data<dyn TokenRepository>>().cloned();
+
+        // Sessions only carry access tokens when cookie mode is configured
+        let cookie_mode = req
+            .app_data::<Data<SessionMode>>()
+            .is_some_and(|mode| *mode.get_ref() == SessionMode::Cookie);
+
         // Clone the service to avoid moving it into the async block
         let service = Rc::clone(&self.service);
+        let scope = self.scope;
+        let optional = self.optional;
 
         Box::pin(async move {
             if let Some(auth) = auth_header {
                 if let Ok(auth_str) = auth.to_str() {
                     let token = auth_str.replace("Bearer ", "");
+
+                    // Personal access tokens are looked up, then checked against the route's scope
+                    if token.starts_with(API_TOKEN_PREFIX) {
+                        let Some(scope) = scope else {
+                            return Err(ErrorForbidden("API tokens are not accepted here"));
+                        };
+                        let tokens = tokens
+                            .ok_or_else(|| ErrorInternalServerError("Token store unavailable"))?;
+                        let api_token = match tokens.use_api_token(&hash_api_token(&token)).await {
+                            Ok(Some(api_token)) => api_token,
+                            Ok(None) => return Err(ErrorUnauthorized("Unauthorized")),
+                            Err(e) => return Err(ErrorInternalServerError(e.to_string())),
+                        };
+                        if !api_token.scopes.contains(&scope) {
+                            return Err(ErrorForbidden("API token lacks the required scope"));
+                        }
+
+                        req.extensions_mut().insert(api_token_claims(&api_token)?);
+                        return service.call(req).await;
+                    }
+
                     // Attempt to decode the JWT token
                     if let Ok(claim) = decode_jwt(token) {
-                        println!("after decoding : {:#?}", claim);
+                        check_not_revoked(tokens, &claim.claims).await?;
+
                         // Insert the claims into request extensions
                         req.extensions_mut().insert(claim.claims);
 
@@ -77,6 +196,27 @@ where
                         return service.call(req).await;
                     }
                 }
+            // Without a bearer token, fall back to a cookie-mode sign-in
+            } else if cookie_mode {
+                let session = req.get_session();
+                let session_token = session.get::<String>(ACCESS_TOKEN_KEY);
+                if optional && matches!(session_token, Ok(None)) {
+                    return service.call(req).await;
+                }
+                if let Ok(Some(token)) = session_token {
+                    // The browser attaches the cookie on its own, so prove the request came from our client
+                    if !verify_csrf(req.request(), &session) {
+                        return Err(ErrorForbidden("Missing or invalid CSRF token"));
+                    }
+
+                    if let Ok(claim) = decode_jwt(token) {
+                        check_not_revoked(tokens, &claim.claims).await?;
+                        req.extensions_mut().insert(claim.claims);
+                        return service.call(req).await;
+                    }
+                }
+            } else if optional {
+                return service.call(req).await;
             }
 
             // Return Unauthorized response if auth is missing or invalid
@@ -1 +1,3 @@
 pub mod auth_middleware;
+pub mod poll_access;
+pub mod rate_limit;
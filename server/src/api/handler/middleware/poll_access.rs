@@ -0,0 +1,74 @@
+use actix_web::{dev::Payload, web::Data, FromRequest, HttpMessage, HttpRequest};
+use futures::future::LocalBoxFuture;
+
+use crate::api::handler::Error;
+use crate::db::{poll_repository::PollRepository, user_repository::UserRepository};
+use crate::models::{auth_jwt::Claims, poll_models::VotingPoll, user_models::Role};
+
+/// Extractor granting access to the poll named by the `{poll_id}` path segment.
+///
+/// Only the poll's creator or an admin can obtain it; anyone else gets a 403.
+/// Routes declaring it must be wrapped in `CheckAuth` so the claims are present.
+pub(crate) struct PollManager {
+    pub poll: VotingPoll,
+}
+
+impl PollManager {
+    pub fn poll_id(&self) -> i64 {
+        self.poll.poll_id.unwrap_or_default()
+    }
+}
+
+impl FromRequest for PollManager {
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let claims = req.extensions().get::<Claims>().cloned();
+        let poll_id = req
+            .match_info()
+            .get("poll_id")
+            .and_then(|poll_id| poll_id.parse::<i64>().ok());
+        let poll_db = req.app_data::<Data<dyn PollRepository>>().cloned();
+        let user_db = req.app_data::<Data<dyn UserRepository>>().cloned();
+
+        Box::pin(async move {
+            let claims = claims.ok_or(Error::Unauthorized)?;
+            let poll_id =
+                poll_id.ok_or_else(|| Error::InvalidInput("Invalid poll ID".to_string()))?;
+            let (poll_db, user_db) = poll_db
+                .zip(user_db)
+                .ok_or_else(|| Error::Database("Repositories not configured".to_string()))?;
+
+            let poll = poll_db
+                .get_poll(poll_id)
+                .await
+                .map_err(|e| Error::Database(e.to_string()))?
+                .ok_or(Error::PollNotFound)?;
+
+            if claims.has_role(Role::Admin) {
+                return Ok(PollManager { poll });
+            }
+
+            let user = user_db
+                .get_user_by_id(claims.uuid.to_string())
+                .await
+                .map_err(|e| Error::Database(e.to_string()))?
+                .ok_or(Error::Unauthorized)?;
+
+            let owns_poll = poll.creator == user.user_name
+                || user
+                    .owned_polls
+                    .as_ref()
+                    .is_some_and(|owned| owned.contains(&poll_id));
+
+            if !owns_poll {
+                return Err(Error::Forbidden(
+                    "Only the poll's creator or an admin can manage this poll".to_string(),
+                ));
+            }
+
+            Ok(PollManager { poll })
+        })
+    }
+}
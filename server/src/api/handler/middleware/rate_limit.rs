@@ -0,0 +1,356 @@
+use actix_session::SessionExt;
+use actix_web::{
+    body::MessageBody,
+    dev::{Path, ResourceDef, Service, ServiceRequest, ServiceResponse, Transform},
+    error::InternalError,
+    http::{
+        header::{AUTHORIZATION, RETRY_AFTER},
+        Method,
+    },
+    web::Data,
+    Error, HttpResponse,
+};
+use futures::future::{ok, LocalBoxFuture, Ready};
+use log::{error, warn};
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::db::rate_limit_store::{RateDecision, RateLimitPolicy, RateLimitStore};
+use crate::models::api_token_models::{hash_api_token, API_TOKEN_PREFIX};
+use crate::models::auth_jwt::decode_jwt;
+use crate::models::session_mode::{SessionMode, ACCESS_TOKEN_KEY};
+use crate::models::user_models::normalize_username;
+
+/// Routes sharing a set of buckets, identified by their full route patterns
+#[derive(Clone)]
+pub struct RouteGroup {
+    name: &'static str,
+    patterns: Vec<&'static str>,
+    methods: Vec<Method>,
+    per_ip: Option<RateLimitPolicy>,
+    per_username: Option<RateLimitPolicy>,
+    per_user: Option<RateLimitPolicy>,
+}
+
+impl RouteGroup {
+    /// # Arguments
+    /// * `name` - Prefix of the group's bucket keys
+    /// * `patterns` - Route patterns as registered, e.g. `/api/auth/start_reg/{username}`
+    pub fn new(name: &'static str, patterns: &[&'static str]) -> Self {
+        Self {
+            name,
+            patterns: patterns.to_vec(),
+            methods: Vec::new(),
+            per_ip: None,
+            per_username: None,
+            per_user: None,
+        }
+    }
+
+    /// Only limits requests with this method; without any, every method is limited
+    pub fn method(mut self, method: Method) -> Self {
+        self.methods.push(method);
+        self
+    }
+
+    /// Limits each client IP address
+    pub fn per_ip(mut self, policy: RateLimitPolicy) -> Self {
+        self.per_ip = Some(policy);
+        self
+    }
+
+    /// Limits each username taken from a route's `{username}` segment
+    pub fn per_username(mut self, policy: RateLimitPolicy) -> Self {
+        self.per_username = Some(policy);
+        self
+    }
+
+    /// Limits each signed-in user, identified by the access token the request carries
+    pub fn per_user(mut self, policy: RateLimitPolicy) -> Self {
+        self.per_user = Some(policy);
+        self
+    }
+
+    /// The pattern of this group matching the request's method and path
+    ///
+    /// Matched here rather than through `match_pattern`, which ignores method
+    /// guards and so can report a different route sharing the path.
+    fn matching_pattern(&self, req: &ServiceRequest) -> Option<&'static str> {
+        if !self.methods.is_empty() && !self.methods.contains(req.method()) {
+            return None;
+        }
+        self.patterns
+            .iter()
+            .copied()
+            .find(|pattern| ResourceDef::new(*pattern).is_match(req.path()))
+    }
+}
+
+// The user behind the request's access token, as a bucket key; the token is
+// only checked for its signature, CheckAuth still decides whether it is accepted
+fn user_key(req: &ServiceRequest) -> Option<String> {
+    let token = match req.headers().get(AUTHORIZATION) {
+        Some(auth) => auth.to_str().ok()?.replace("Bearer ", ""),
+        None => {
+            let cookie_mode = req
+                .app_data::<Data<SessionMode>>()
+                .is_some_and(|mode| *mode.get_ref() == SessionMode::Cookie);
+            if !cookie_mode {
+                return None;
+            }
+            req.get_session().get::<String>(ACCESS_TOKEN_KEY).ok()??
+        }
+    };
+
+    if token.starts_with(API_TOKEN_PREFIX) {
+        return Some(format!("token:{}", hash_api_token(&token)));
+    }
+    decode_jwt(token)
+        .ok()
+        .map(|data| format!("account:{}", data.claims.uuid))
+}
+
+// Middleware struct; wraps the whole app so patterns are matched on full paths
+#[derive(Clone)]
+pub struct RateLimit {
+    store: Arc<dyn RateLimitStore>,
+    groups: Rc<Vec<RouteGroup>>,
+    trust_forwarded: bool,
+}
+
+impl RateLimit {
+    pub fn new(store: Arc<dyn RateLimitStore>) -> Self {
+        Self {
+            store,
+            groups: Rc::new(Vec::new()),
+            trust_forwarded: false,
+        }
+    }
+
+    pub fn group(mut self, group: RouteGroup) -> Self {
+        Rc::make_mut(&mut self.groups).push(group);
+        self
+    }
+
+    /// Keys IP buckets on `Forwarded`/`X-Forwarded-For` instead of the peer address;
+    /// only safe behind a proxy that overwrites those headers
+    pub fn trust_forwarded(mut self, trust_forwarded: bool) -> Self {
+        self.trust_forwarded = trust_forwarded;
+        self
+    }
+}
+
+// Implement `Transform` trait for `RateLimit`
+impl<S, B> Transform<S, ServiceRequest> for RateLimit
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = RateLimitMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(RateLimitMiddleware {
+            service: Rc::new(service),
+            limits: self.clone(),
+        })
+    }
+}
+
+// Inner middleware struct to hold the service
+pub struct RateLimitMiddleware<S> {
+    service: Rc<S>,
+    limits: RateLimit,
+}
+
+impl<S> RateLimitMiddleware<S> {
+    // Bucket keys and policies that apply to the request
+    fn buckets(&self, req: &ServiceRequest) -> Vec<(String, RateLimitPolicy)> {
+        let Some((group, pattern)) = self
+            .limits
+            .groups
+            .iter()
+            .find_map(|group| Some((group, group.matching_pattern(req)?)))
+        else {
+            return Vec::new();
+        };
+
+        let mut buckets = Vec::new();
+        if let Some(policy) = group.per_ip {
+            let connection_info = req.connection_info();
+            let ip = if self.limits.trust_forwarded {
+                connection_info.realip_remote_addr()
+            } else {
+                connection_info.peer_addr()
+            }
+            .unwrap_or("unknown")
+            .to_string();
+            buckets.push((format!("{}:ip:{}", group.name, ip), policy));
+        }
+        if let Some(policy) = group.per_username {
+            // Routing has not run yet, so capture the segment from the matched pattern
+            let mut path = Path::new(req.path().to_string());
+            if ResourceDef::new(pattern).capture_match_info(&mut path) {
+                if let Some(username) = path.get("username") {
+                    let username =
+                        normalize_username(username).unwrap_or_else(|_| username.to_lowercase());
+                    buckets.push((format!("{}:user:{}", group.name, username), policy));
+                }
+            }
+        }
+        if let Some(policy) = group.per_user {
+            if let Some(user) = user_key(req) {
+                buckets.push((format!("{}:{}", group.name, user), policy));
+            }
+        }
+        buckets
+    }
+}
+
+// Implement `Service` trait for `RateLimitMiddleware`
+impl<S, B> Service<ServiceRequest> for RateLimitMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(
+        &self,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let buckets = self.buckets(&req);
+        let store = Arc::clone(&self.limits.store);
+        let service = Rc::clone(&self.service);
+
+        Box::pin(async move {
+            let mut retry_after: Option<Duration> = None;
+            for (key, policy) in &buckets {
+                match store.acquire(key, policy).await {
+                    Ok(RateDecision::Allowed) => {}
+                    Ok(RateDecision::Limited { retry_after: wait }) => {
+                        warn!("Rate limit exceeded for {}", key);
+                        retry_after = retry_after.max(Some(wait));
+                    }
+                    // A broken store must not lock everyone out
+                    Err(e) => error!("Rate limit store failed for {}: {}", key, e),
+                }
+            }
+
+            if let Some(wait) = retry_after {
+                let response = HttpResponse::TooManyRequests()
+                    .insert_header((RETRY_AFTER, wait.as_secs_f64().ceil().max(1.0) as u64))
+                    .json("Too many requests");
+                return Err(InternalError::from_response("Too many requests", response).into());
+            }
+
+            service.call(req).await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::memory_rate_limit_store::MemoryRateLimitStore;
+    use actix_web::{http::StatusCode, test, web, App};
+
+    fn limiter() -> RateLimit {
+        RateLimit::new(Arc::new(MemoryRateLimitStore::new())).group(
+            RouteGroup::new("votes", &["/api/polls/vote"])
+                .method(Method::POST)
+                .per_ip(RateLimitPolicy::new(1, Duration::from_secs(60))),
+        )
+    }
+
+    // `POST /api/polls/vote` shares its path with `GET /api/polls/{poll_id}`,
+    // which is registered first, as in the real app
+    #[actix_web::test]
+    async fn limits_votes_despite_overlapping_get_route() {
+        let app = test::init_service(
+            App::new().wrap(limiter()).service(
+                web::scope("/api")
+                    .route("/polls/{poll_id}", web::get().to(HttpResponse::Ok))
+                    .route("/polls/vote", web::post().to(HttpResponse::Ok)),
+            ),
+        )
+        .await;
+
+        let vote = || {
+            test::TestRequest::post()
+                .uri("/api/polls/vote")
+                .to_request()
+        };
+        let first = test::call_service(&app, vote()).await;
+        assert_eq!(first.status(), StatusCode::OK);
+
+        let second = test::try_call_service(&app, vote())
+            .await
+            .expect_err("second vote should be limited");
+        let response = second.error_response();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert!(response.headers().contains_key(RETRY_AFTER));
+    }
+
+    #[actix_web::test]
+    async fn leaves_other_methods_on_the_path_alone() {
+        let app = test::init_service(
+            App::new()
+                .wrap(limiter())
+                .route("/api/polls/{poll_id}", web::get().to(HttpResponse::Ok)),
+        )
+        .await;
+
+        for _ in 0..3 {
+            let req = test::TestRequest::get().uri("/api/polls/vote").to_request();
+            let res = test::call_service(&app, req).await;
+            assert_eq!(res.status(), StatusCode::OK);
+        }
+    }
+
+    #[actix_web::test]
+    async fn limits_each_api_token_separately() {
+        let app = test::init_service(
+            App::new()
+                .wrap(
+                    RateLimit::new(Arc::new(MemoryRateLimitStore::new())).group(
+                        RouteGroup::new("votes", &["/api/polls/vote"])
+                            .per_user(RateLimitPolicy::new(1, Duration::from_secs(60))),
+                    ),
+                )
+                .route("/api/polls/vote", web::post().to(HttpResponse::Ok)),
+        )
+        .await;
+        let vote = |token: &str| {
+            test::TestRequest::post()
+                .uri("/api/polls/vote")
+                .insert_header((
+                    AUTHORIZATION,
+                    format!("Bearer {}{}", API_TOKEN_PREFIX, token),
+                ))
+                .to_request()
+        };
+
+        assert!(test::try_call_service(&app, vote("first")).await.is_ok());
+        assert!(test::try_call_service(&app, vote("second")).await.is_ok());
+        let limited = test::try_call_service(&app, vote("first"))
+            .await
+            .expect_err("same token should be limited");
+        assert_eq!(
+            limited.error_response().status(),
+            StatusCode::TOO_MANY_REQUESTS
+        );
+    }
+}
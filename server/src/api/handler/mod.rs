@@ -1,5 +1,8 @@
+pub mod account_routes;
+pub mod admin_routes;
 pub mod auth_routes;
 pub mod poll_routes;
+pub mod profile_routes;
 
 use actix_web::http::StatusCode;
 use thiserror::Error;
@@ -18,6 +21,11 @@ pub(crate) enum Error {
     Unknown(WebauthnError),
     #[error("Corrupt session")]
     CorruptSession,
+    #[error("Ceremony expired")]
+    CeremonyExpired,
+    /// No ceremony of this kind is in progress for the caller, or it was already finished
+    #[error("No matching ceremony in progress")]
+    CeremonyNotFound,
     #[error("Bad request: {0}")]
     BadRequest(#[from] WebauthnError),
     #[error("Database error: {0}")]
@@ -28,9 +36,32 @@ pub(crate) enum Error {
     InvalidInput(String),
     #[error("Token error: {0}")]
     Token(String),
+    #[error("Unauthorized")]
+    Unauthorized,
+    #[error("Forbidden: {0}")]
+    Forbidden(String),
+    #[error("Poll not found")]
+    PollNotFound,
+    #[error("Credential not found")]
+    CredentialNotFound,
+    #[error("API token not found")]
+    ApiTokenNotFound,
+    #[error("Conflict: {0}")]
+    Conflict(String),
 }
 impl actix_web::ResponseError for Error {
     fn status_code(&self) -> StatusCode {
-        StatusCode::INTERNAL_SERVER_ERROR
+        match self {
+            Error::Unauthorized => StatusCode::UNAUTHORIZED,
+            Error::Forbidden(_) => StatusCode::FORBIDDEN,
+            Error::UserNotFound
+            | Error::PollNotFound
+            | Error::CredentialNotFound
+            | Error::ApiTokenNotFound => StatusCode::NOT_FOUND,
+            Error::Conflict(_) => StatusCode::CONFLICT,
+            Error::InvalidInput(_) | Error::CeremonyNotFound => StatusCode::BAD_REQUEST,
+            Error::CeremonyExpired => StatusCode::GONE,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
     }
 }
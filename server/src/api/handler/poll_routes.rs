@@ -1,7 +1,14 @@
+use crate::api::handler::middleware::{auth_middleware::CheckAuth, poll_access::PollManager};
 use crate::db::poll_repository::PollRepository;
 use crate::db::user_repository::UserRepository;
-use crate::models::poll_models::{ResultsQuery, ServerEvents, VoteRequest, VotingPollInput};
-use crate::models::user_models::Votes;
+use crate::models::api_token_models::Scope;
+use crate::models::auth_jwt::{Claims, RecentAuth};
+use crate::models::poll_models::{
+    Ballot, PollResults, PollView, ResultsQuery, ServerEvents, VoteRequest, VotingPoll,
+    VotingPollInput,
+};
+use crate::models::tabulation::tabulator;
+use crate::models::user_models::User;
 use actix_web::body::MessageBody;
 use actix_web::{
     delete, get, post,
@@ -9,6 +16,7 @@ use actix_web::{
     HttpResponse,
 };
 use serde_json::json;
+use std::collections::HashMap;
 use tokio::sync::mpsc;
 use tokio_stream::StreamExt;
 use tracing::info;
@@ -19,49 +27,131 @@ fn internal_server_error<T: ToString>(err: T) -> HttpResponse {
     HttpResponse::InternalServerError().body(err.to_string())
 }
 
+// Resolve the authenticated user behind the JWT claims
+async fn acting_user(
+    user_db: &Data<dyn UserRepository>,
+    claims: &Claims,
+) -> Result<User, HttpResponse> {
+    match user_db.get_user_by_id(claims.uuid.to_string()).await {
+        Ok(Some(user)) => Ok(user),
+        Ok(None) => Err(HttpResponse::Unauthorized().json(json!({
+            "error": "Authenticated user no longer exists"
+        }))),
+        Err(err) => Err(internal_server_error(err)),
+    }
+}
+
+// Attach creator display names to polls; usernames stand in if the lookup fails
+async fn poll_views(user_db: &Data<dyn UserRepository>, polls: Vec<VotingPoll>) -> Vec<PollView> {
+    let mut creators: Vec<String> = polls.iter().map(|poll| poll.creator.clone()).collect();
+    creators.sort();
+    creators.dedup();
+
+    let display_names = user_db.display_names(creators).await.unwrap_or_else(|err| {
+        eprintln!("Error: failed to look up display names: {}", err);
+        HashMap::new()
+    });
+
+    polls
+        .into_iter()
+        .map(|poll| PollView::new(poll, &display_names))
+        .collect()
+}
+
+// Attach the creator's display name to a single poll
+async fn poll_view(user_db: &Data<dyn UserRepository>, poll: VotingPoll) -> PollView {
+    poll_views(user_db, vec![poll])
+        .await
+        .pop()
+        .expect("one view per poll")
+}
+
+// Build a poll's results, counting its ballots when the voting method calls for it
+async fn poll_results_view(
+    db: &Data<dyn PollRepository>,
+    user_db: &Data<dyn UserRepository>,
+    poll: VotingPoll,
+) -> Result<PollResults, Box<dyn std::error::Error + Send + Sync>> {
+    let tabulation = match (poll.poll_id, tabulator(&poll.voting_method)) {
+        (Some(poll_id), Some(tabulator)) => {
+            let ballots = db.get_ballots(poll_id).await?;
+            Some(tabulator.tabulate(&poll.options, &ballots))
+        }
+        _ => None,
+    };
+
+    Ok(PollResults {
+        poll: poll_view(user_db, poll).await,
+        tabulation,
+    })
+}
+
 // Add a new poll
-#[post("/polls")]
+#[post("/polls", wrap = "CheckAuth::scoped(Scope::PollsWrite)")]
 pub async fn add_polls(
     db: Data<dyn PollRepository>,
+    user_db: Data<dyn UserRepository>,
+    claims: Claims,
     request: Json<VotingPollInput>,
 ) -> HttpResponse {
     info!("Received Poll Data: {:#?}", request);
 
-    match db.create_poll(request.into_inner()).await {
-        Ok(poll) => HttpResponse::Ok().json(poll),
+    let mut request = request.into_inner();
+    if let Err(err) = request.validate() {
+        return HttpResponse::BadRequest().json(json!({ "error": err.to_string() }));
+    }
+
+    let creator = match acting_user(&user_db, &claims).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+    let display_names = HashMap::from([(
+        creator.user_name.clone(),
+        creator.display_name().to_string(),
+    )]);
+
+    match db.create_poll(request, creator.user_name).await {
+        Ok(poll) => HttpResponse::Ok().json(PollView::new(poll, &display_names)),
         Err(err) => internal_server_error(err),
     }
 }
 
 // Fetch poll(s) based on ID
-#[get("/polls/{poll_id}")]
-pub async fn fetch_polls(db: Data<dyn PollRepository>, path: Path<i64>) -> HttpResponse {
+#[get("/polls/{poll_id}", wrap = "CheckAuth::optional(Scope::PollsRead)")]
+pub async fn fetch_polls(
+    db: Data<dyn PollRepository>,
+    user_db: Data<dyn UserRepository>,
+    path: Path<i64>,
+) -> HttpResponse {
     let poll_id = path.into_inner();
     if poll_id == 0 {
         match db.fetch_all().await {
-            Ok(polls) => HttpResponse::Ok().json(polls),
+            Ok(polls) => HttpResponse::Ok().json(poll_views(&user_db, polls).await),
             Err(err) => internal_server_error(err),
         }
     } else {
         match db.get_poll(poll_id).await {
-            Ok(poll) => HttpResponse::Ok().json(poll),
+            Ok(Some(poll)) => HttpResponse::Ok().json(poll_view(&user_db, poll).await),
+            Ok(None) => HttpResponse::Ok().json(None::<PollView>),
             Err(err) => internal_server_error(err),
         }
     }
 }
 
 // Cast a vote
-#[post("/polls/vote")]
+#[post("/polls/vote", wrap = "CheckAuth::scoped(Scope::VotesWrite)")]
 pub async fn cast_vote(
     db: Data<dyn PollRepository>,
     user_db: Data<dyn UserRepository>,
+    claims: Claims,
     body: Json<VoteRequest>,
 ) -> HttpResponse {
-    let VoteRequest {
-        poll_id,
-        option_id,
-        username,
-    } = body.into_inner();
+    let poll_id = body.poll_id;
+
+    let username = match acting_user(&user_db, &claims).await {
+        Ok(user) => user.user_name,
+        Err(response) => return response,
+    };
 
     // Check if the user has already voted in this poll
     match user_db.has_voted(username.clone(), poll_id).await {
@@ -80,35 +170,43 @@ pub async fn cast_vote(
         }
     }
 
-    // Create a vote record for the user
-    let vote = Votes { poll_id, option_id };
-
-    // Update user's voting history
-    if let Err(err) = user_db.update_user(username.clone(), vote).await {
-        return HttpResponse::InternalServerError().json(json!({
-            "error": format!("Failed to update user's voting history: {}", err)
+    // The ballot must fit the poll's voting method
+    let poll = match db.get_poll(poll_id).await {
+        Ok(Some(poll)) => poll,
+        Ok(None) => {
+            return HttpResponse::NotFound().json(json!({ "error": "Poll not found" }));
+        }
+        Err(err) => return internal_server_error(err),
+    };
+    let Some(weight) = poll.voter_weight(&username) else {
+        return HttpResponse::Forbidden().json(json!({
+            "error": "You are not eligible to vote in this poll"
         }));
+    };
+    if let Err(err) = poll.voting_method.validate_ballot(&body, &poll.options) {
+        return HttpResponse::BadRequest().json(json!({ "error": err.to_string() }));
     }
+    let ballot = Ballot::new(&body, weight);
 
-    // Record the vote in the poll
-    match db.vote_poll(poll_id, option_id, username.clone()).await {
+    // Record the vote in the poll; the repository adds it to the user's voting history
+    let increments = poll.voting_method.tally_increments(&ballot);
+    match db.vote_poll(ballot, increments, username.clone()).await {
         Ok(_) => HttpResponse::Ok().json(json!({
             "message": "Vote cast successfully"
         })),
-        Err(err) => {
-            // If updating the poll fails, roll back the user's voting history
-
-            HttpResponse::InternalServerError().json(json!({
-                "error": format!("Failed to cast vote: {}", err)
-            }))
-        }
+        Err(err) => HttpResponse::InternalServerError().json(json!({
+            "error": format!("Failed to cast vote: {}", err)
+        })),
     }
 }
 
 // Reset a poll
-#[post("/polls/{poll_id}/reset")]
-pub async fn reset_vote(db: Data<dyn PollRepository>, path: Path<i64>) -> HttpResponse {
-    let poll_id = path.into_inner();
+#[post(
+    "/polls/{poll_id}/reset",
+    wrap = "CheckAuth::scoped(Scope::PollsWrite)"
+)]
+pub async fn reset_vote(db: Data<dyn PollRepository>, manager: PollManager) -> HttpResponse {
+    let poll_id = manager.poll_id();
     match db.update_poll(poll_id, "reset".to_string()).await {
         Ok(_) => HttpResponse::Ok().body("Poll reset successfully"),
         Err(err) => internal_server_error(err),
@@ -116,9 +214,12 @@ pub async fn reset_vote(db: Data<dyn PollRepository>, path: Path<i64>) -> HttpRe
 }
 
 // Close a poll
-#[post("/polls/{poll_id}/close")]
-pub async fn close_poll(db: Data<dyn PollRepository>, path: Path<i64>) -> HttpResponse {
-    let poll_id = path.into_inner();
+#[post(
+    "/polls/{poll_id}/close",
+    wrap = "CheckAuth::scoped(Scope::PollsWrite)"
+)]
+pub async fn close_poll(db: Data<dyn PollRepository>, manager: PollManager) -> HttpResponse {
+    let poll_id = manager.poll_id();
     match db.update_poll(poll_id, "close".to_string()).await {
         Ok(_) => HttpResponse::Ok().body("Poll closed successfully"),
         Err(err) => internal_server_error(err),
@@ -126,9 +227,13 @@ pub async fn close_poll(db: Data<dyn PollRepository>, path: Path<i64>) -> HttpRe
 }
 
 // Fetch poll results (live or static)
-#[get("/polls/{poll_id}/results")]
+#[get(
+    "/polls/{poll_id}/results",
+    wrap = "CheckAuth::optional(Scope::PollsRead)"
+)]
 pub async fn poll_results(
     db: Data<dyn PollRepository>,
+    user_db: Data<dyn UserRepository>,
     path: Path<i64>,
     query: Query<ResultsQuery>,
 ) -> HttpResponse {
@@ -136,12 +241,17 @@ pub async fn poll_results(
     if query.live {
         let (tx, rx) = mpsc::channel(1024);
         let db_clone = db.clone();
+        let user_db = user_db.clone();
 
         tokio::spawn(async move {
             loop {
                 match db_clone.get_poll(poll_id).await {
                     Ok(poll) => {
-                        let data = serde_json::to_string(&poll).unwrap_or_default();
+                        let results = match poll {
+                            Some(poll) => poll_results_view(&db_clone, &user_db, poll).await.ok(),
+                            None => None,
+                        };
+                        let data = serde_json::to_string(&results).unwrap_or_default();
                         if tx.send(format!("data: {}\n\n", data)).await.is_err() {
                             break;
                         }
@@ -170,33 +280,25 @@ pub async fn poll_results(
     }
 
     match db.get_poll(poll_id).await {
-        Ok(poll) => HttpResponse::Ok().json(poll),
+        Ok(Some(poll)) => match poll_results_view(&db, &user_db, poll).await {
+            Ok(results) => HttpResponse::Ok().json(results),
+            Err(err) => internal_server_error(err),
+        },
+        Ok(None) => HttpResponse::Ok().json(None::<PollResults>),
         Err(_) => HttpResponse::NotFound().json(json!({ "error": "Poll not found" })),
     }
 }
 
-// Delete a poll
-#[delete("/polls/{poll_id}")]
-pub async fn delete_poll(db: Data<dyn PollRepository>, path: Path<i64>) -> HttpResponse {
-    let poll_id = path.into_inner();
-
-    // Check if the poll exists in the database
-    match db.get_poll(poll_id).await {
-        Ok(Some(_)) => {
-            // Proceed to delete the poll if it exists
-            match db.delete_poll(poll_id).await {
-                Ok(_) => HttpResponse::Ok().body("Poll deleted successfully"),
-                Err(err) => internal_server_error(err),
-            }
-        }
-        Ok(None) => {
-            // Poll ID not found in the database
-            HttpResponse::NotFound()
-                .body("Poll not found. It might have been deleted or never created.")
-        }
-        Err(err) => {
-            // Error occurred while checking for poll existence
-            internal_server_error(err)
-        }
+// Delete a poll; the manager must have verified themselves recently
+#[delete("/polls/{poll_id}", wrap = "CheckAuth::scoped(Scope::PollsWrite)")]
+pub async fn delete_poll(
+    db: Data<dyn PollRepository>,
+    manager: PollManager,
+    _verified: RecentAuth,
+) -> HttpResponse {
+    // The poll is known to exist: `PollManager` answers 404 otherwise
+    match db.delete_poll(manager.poll_id()).await {
+        Ok(_) => HttpResponse::Ok().body("Poll deleted successfully"),
+        Err(err) => internal_server_error(err),
     }
 }
@@ -0,0 +1,126 @@
+use actix_web::{
+    delete, get,
+    http::header::{CacheControl, CacheDirective, ContentType},
+    patch, put,
+    web::{Bytes, Data, Json, Path},
+    HttpResponse,
+};
+use log::info;
+
+use crate::{
+    api::handler::middleware::auth_middleware::CheckAuth,
+    api::handler::{Error, WebResult},
+    db::user_repository::UserRepository,
+    models::{
+        auth_jwt::Claims,
+        profile_models::{Avatar, MeResponse, UpdateProfileRequest},
+        user_models::{normalize_username, User},
+    },
+};
+
+/// Loads the user behind the access token
+async fn current_user(db: &dyn UserRepository, claims: &Claims) -> WebResult<User> {
+    db.get_user_by_id(claims.uuid.to_string())
+        .await
+        .map_err(|e| Error::Database(e.to_string()))?
+        .ok_or(Error::Unauthorized)
+}
+
+/// The caller's account and profile
+#[get("", wrap = "CheckAuth")]
+pub(crate) async fn me(
+    claims: Claims,
+    db: Data<dyn UserRepository>,
+) -> WebResult<Json<MeResponse>> {
+    let user = current_user(db.as_ref(), &claims).await?;
+    Ok(Json(MeResponse::from(&user)))
+}
+
+/// Updates the fields of the caller's profile present in the body
+#[patch("", wrap = "CheckAuth")]
+pub(crate) async fn update_me(
+    claims: Claims,
+    req: Json<UpdateProfileRequest>,
+    db: Data<dyn UserRepository>,
+) -> WebResult<Json<MeResponse>> {
+    let mut user = current_user(db.as_ref(), &claims).await?;
+    user.profile
+        .apply(req.into_inner())
+        .map_err(|e| Error::InvalidInput(e.to_string()))?;
+
+    let updated = db
+        .update_profile(user.user_id.clone(), user.profile.clone())
+        .await
+        .map_err(|e| Error::Database(e.to_string()))?;
+    if !updated {
+        return Err(Error::Unauthorized);
+    }
+
+    info!("Updated profile of user {}", user.user_id);
+    Ok(Json(MeResponse::from(&user)))
+}
+
+/// Replaces the caller's avatar with the image in the request body
+#[put("avatar", wrap = "CheckAuth")]
+pub(crate) async fn upload_avatar(
+    claims: Claims,
+    body: Bytes,
+    db: Data<dyn UserRepository>,
+) -> WebResult<Json<MeResponse>> {
+    let mut user = current_user(db.as_ref(), &claims).await?;
+    let avatar =
+        Avatar::from_upload(body.to_vec()).map_err(|e| Error::InvalidInput(e.to_string()))?;
+
+    db.set_avatar(user.user_id.clone(), Some(avatar.clone()))
+        .await
+        .map_err(|e| Error::Database(e.to_string()))?;
+
+    info!("Updated avatar of user {}", user.user_id);
+    user.profile.avatar = Some(avatar);
+    Ok(Json(MeResponse::from(&user)))
+}
+
+#[delete("avatar", wrap = "CheckAuth")]
+pub(crate) async fn delete_avatar(
+    claims: Claims,
+    db: Data<dyn UserRepository>,
+) -> WebResult<Json<MeResponse>> {
+    let mut user = current_user(db.as_ref(), &claims).await?;
+
+    db.set_avatar(user.user_id.clone(), None)
+        .await
+        .map_err(|e| Error::Database(e.to_string()))?;
+
+    user.profile.avatar = None;
+    Ok(Json(MeResponse::from(&user)))
+}
+
+/// Serves a user's avatar to anyone who can see their polls
+#[get("{username}/avatar")]
+pub(crate) async fn user_avatar(
+    username: Path<String>,
+    db: Data<dyn UserRepository>,
+) -> WebResult<HttpResponse> {
+    let username = normalize_username(&username).map_err(|_| Error::UserNotFound)?;
+    let avatar = db
+        .get_user(username)
+        .await
+        .map_err(|e| Error::Database(e.to_string()))?
+        .and_then(|user| user.profile.avatar)
+        .ok_or(Error::UserNotFound)?;
+
+    let content_type = avatar
+        .content_type
+        .parse()
+        .map_err(|_| Error::Database("Corrupt avatar".to_string()))?;
+
+    // Avatar URLs carry a version, so a cached copy never goes stale
+    Ok(HttpResponse::Ok()
+        .insert_header(ContentType(content_type))
+        .insert_header(CacheControl(vec![
+            CacheDirective::Public,
+            CacheDirective::MaxAge(86400),
+        ]))
+        .insert_header(("X-Content-Type-Options", "nosniff"))
+        .body(avatar.data.bytes))
+}
@@ -0,0 +1,25 @@
+use crate::models::auth_event::{AuthEvent, AuthEventQuery};
+use async_trait::async_trait;
+
+/// Append-only store of authentication events; entries are never updated or deleted
+#[async_trait]
+pub trait AuditRepository: Send + Sync {
+    async fn record(
+        &self,
+        event: AuthEvent,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>>;
+
+    /// The user's most recent sign-in attempts, newest first
+    async fn sign_in_history(
+        &self,
+        user_id: &str,
+        limit: i64,
+    ) -> Result<Vec<AuthEvent>, Box<dyn std::error::Error + Send + Sync>>;
+
+    /// Events matching every filter that is set, newest first
+    async fn query(
+        &self,
+        query: AuthEventQuery,
+        limit: i64,
+    ) -> Result<Vec<AuthEvent>, Box<dyn std::error::Error + Send + Sync>>;
+}
@@ -0,0 +1,36 @@
+use async_trait::async_trait;
+use thiserror::Error;
+
+#[allow(dead_code)]
+#[derive(Debug, Error)]
+pub enum StateError {
+    #[error("State not found")]
+    NotFound,
+    #[error("State expired")]
+    Expired,
+    #[error("Lock acquisition failed")]
+    LockError,
+    #[error("Storage error: {0}")]
+    Storage(String),
+}
+
+/// Storage for in-flight WebAuthn ceremonies, keyed by an opaque ceremony ID
+#[async_trait]
+pub trait ChallengeStore<T>: Send + Sync {
+    /// Stores the state of a new ceremony and returns the ceremony ID it is stored under
+    async fn insert(&self, state: T) -> Result<String, StateError>;
+
+    /// Retrieves and removes a ceremony, so each one can only be finished once
+    async fn take(&self, ceremony_id: &str) -> Result<T, StateError>;
+
+    async fn remove(&self, ceremony_id: &str) -> Result<(), StateError>;
+
+    /// Removes ceremonies older than the configured TTL and returns how many were swept
+    async fn cleanup_expired(&self) -> Result<usize, StateError>;
+
+    /// Number of ceremonies currently in flight
+    async fn pending(&self) -> Result<usize, StateError>;
+
+    /// Total number of expired ceremonies swept by this instance since startup
+    fn swept_count(&self) -> u64;
+}
@@ -3,6 +3,10 @@ pub struct DbConfig {
     pub db_type: String,
     pub connection_string: String,
     pub database_name: String,
+    /// Backend for in-flight WebAuthn ceremonies: "memory" or "mongodb"
+    pub challenge_store: String,
+    /// Backend for rate limit buckets: "memory" or "mongodb"
+    pub rate_limit_store: String,
 }
 
 #[allow(dead_code)]
@@ -12,6 +16,8 @@ impl DbConfig {
             db_type: db_type.to_string(),
             connection_string: connection_string.to_string(),
             database_name: database_name.to_string(),
+            challenge_store: "memory".to_string(),
+            rate_limit_store: "memory".to_string(),
         }
     }
 
@@ -26,6 +32,8 @@ pub struct DbConfigBuilder {
     db_type: Option<String>,
     connection_string: Option<String>,
     database_name: Option<String>,
+    challenge_store: Option<String>,
+    rate_limit_store: Option<String>,
 }
 
 #[allow(dead_code)]
@@ -45,6 +53,16 @@ impl DbConfigBuilder {
         self
     }
 
+    pub fn challenge_store(mut self, challenge_store: &str) -> Self {
+        self.challenge_store = Some(challenge_store.to_string());
+        self
+    }
+
+    pub fn rate_limit_store(mut self, rate_limit_store: &str) -> Self {
+        self.rate_limit_store = Some(rate_limit_store.to_string());
+        self
+    }
+
     pub fn build(self) -> Result<DbConfig, &'static str> {
         Ok(DbConfig {
             db_type: self.db_type.ok_or("db_type is required")?,
@@ -52,6 +70,10 @@ impl DbConfigBuilder {
                 .connection_string
                 .ok_or("connection_string is required")?,
             database_name: self.database_name.ok_or("database_name is required")?,
+            challenge_store: self.challenge_store.unwrap_or_else(|| "memory".to_string()),
+            rate_limit_store: self
+                .rate_limit_store
+                .unwrap_or_else(|| "memory".to_string()),
         })
     }
 }
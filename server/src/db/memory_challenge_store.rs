@@ -0,0 +1,90 @@
+use crate::db::challenge_store::{ChallengeStore, StateError};
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime};
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+struct Entry<T> {
+    state: T,
+    created_at: SystemTime,
+}
+
+/// Process-local challenge store, suitable for a single server instance
+pub struct MemoryChallengeStore<T> {
+    state_map: Mutex<HashMap<String, Entry<T>>>,
+    ttl: Duration,
+    swept: AtomicU64,
+}
+
+impl<T> MemoryChallengeStore<T> {
+    /// Creates a new instance of `MemoryChallengeStore` whose ceremonies expire after `ttl`
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            state_map: Mutex::new(HashMap::new()),
+            ttl,
+            swept: AtomicU64::new(0),
+        }
+    }
+
+    /// Whether the entry was created longer ago than the configured TTL
+    fn is_expired(&self, entry: &Entry<T>, now: SystemTime) -> bool {
+        now.duration_since(entry.created_at)
+            .map(|age| age >= self.ttl)
+            .unwrap_or(true)
+    }
+}
+
+#[async_trait::async_trait]
+impl<T: Send + Sync> ChallengeStore<T> for MemoryChallengeStore<T> {
+    async fn insert(&self, state: T) -> Result<String, StateError> {
+        let entry = Entry {
+            state,
+            created_at: SystemTime::now(),
+        };
+
+        let ceremony_id = Uuid::new_v4().to_string();
+        let mut map = self.state_map.lock().await;
+        map.insert(ceremony_id.clone(), entry);
+
+        Ok(ceremony_id)
+    }
+
+    async fn take(&self, ceremony_id: &str) -> Result<T, StateError> {
+        let mut map = self.state_map.lock().await;
+        let entry = map.remove(ceremony_id).ok_or(StateError::NotFound)?;
+
+        if self.is_expired(&entry, SystemTime::now()) {
+            return Err(StateError::Expired);
+        }
+
+        Ok(entry.state)
+    }
+
+    async fn remove(&self, ceremony_id: &str) -> Result<(), StateError> {
+        let mut map = self.state_map.lock().await;
+        map.remove(ceremony_id);
+        Ok(())
+    }
+
+    async fn cleanup_expired(&self) -> Result<usize, StateError> {
+        let mut map = self.state_map.lock().await;
+        let now = SystemTime::now();
+        let before = map.len();
+
+        map.retain(|_, entry| !self.is_expired(entry, now));
+
+        let swept = before - map.len();
+        self.swept.fetch_add(swept as u64, Ordering::Relaxed);
+        Ok(swept)
+    }
+
+    async fn pending(&self) -> Result<usize, StateError> {
+        Ok(self.state_map.lock().await.len())
+    }
+
+    fn swept_count(&self) -> u64 {
+        self.swept.load(Ordering::Relaxed)
+    }
+}
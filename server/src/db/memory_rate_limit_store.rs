@@ -0,0 +1,62 @@
+use crate::db::rate_limit_store::{RateDecision, RateLimitPolicy, RateLimitStore};
+
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::time::Instant;
+
+/// Number of buckets tracked before idle ones are dropped
+const MAX_TRACKED_KEYS: usize = 100_000;
+
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+/// Process-local rate limit buckets, suitable for a single server instance
+#[derive(Default)]
+pub struct MemoryRateLimitStore {
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl MemoryRateLimitStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait::async_trait]
+impl RateLimitStore for MemoryRateLimitStore {
+    async fn acquire(
+        &self,
+        key: &str,
+        policy: &RateLimitPolicy,
+    ) -> Result<RateDecision, Box<dyn std::error::Error + Send + Sync>> {
+        let now = Instant::now();
+        let capacity = policy.capacity as f64;
+        let mut buckets = self.buckets.lock();
+
+        // A bucket that has refilled completely is the same as no bucket at all
+        if buckets.len() >= MAX_TRACKED_KEYS {
+            let full_refill = policy.full_refill();
+            buckets.retain(|_, bucket| now.duration_since(bucket.updated_at) < full_refill);
+        }
+
+        let bucket = buckets.entry(key.to_string()).or_insert(Bucket {
+            tokens: capacity,
+            updated_at: now,
+        });
+
+        let elapsed = now.duration_since(bucket.updated_at).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * policy.refill_rate()).min(capacity);
+        bucket.updated_at = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(RateDecision::Allowed)
+        } else {
+            Ok(RateDecision::Limited {
+                retry_after: policy.retry_after(bucket.tokens),
+            })
+        }
+    }
+}
@@ -1,12 +1,35 @@
+pub mod audit_repository;
+pub mod challenge_store;
 pub mod db_config;
+pub mod memory_challenge_store;
+pub mod memory_rate_limit_store;
+pub mod mongo_audit_repo;
+pub mod mongo_challenge_store;
 pub mod mongo_poll_repo;
+pub mod mongo_rate_limit_store;
+pub mod mongo_token_repo;
 pub mod mongo_user_repo;
 pub mod poll_repository;
+pub mod rate_limit_store;
+pub mod token_repository;
 pub mod user_repository;
 
 use crate::db::{mongo_poll_repo::MongoPollRepo, poll_repository::PollRepository};
+use audit_repository::AuditRepository;
+use challenge_store::ChallengeStore;
 use db_config::DbConfig;
+use memory_challenge_store::MemoryChallengeStore;
+use memory_rate_limit_store::MemoryRateLimitStore;
+use mongo_audit_repo::MongoAuditRepo;
+use mongo_challenge_store::MongoChallengeStore;
+use mongo_rate_limit_store::MongoRateLimitStore;
+use mongo_token_repo::MongoTokenRepo;
 use mongo_user_repo::MongoUserRepo;
+use rate_limit_store::RateLimitStore;
+use serde::{de::DeserializeOwned, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+use token_repository::TokenRepository;
 use user_repository::UserRepository;
 
 /// Initializes the poll repository based on the provided database configuration.
@@ -46,3 +69,90 @@ pub async fn init_user_repo(
         _ => panic!("Unsupported database type: {}", config.db_type),
     }
 }
+
+/// Initializes the token repository based on the provided database configuration.
+///
+/// # Arguments
+/// * `config` - The `DbConfig` containing database type and connection details.
+///
+/// # Returns
+/// * An instance of a type implementing `TokenRepository`.
+///
+/// # Panics
+/// * If the database type is unsupported.
+pub async fn init_token_repo(
+    config: DbConfig,
+) -> Result<impl TokenRepository, Box<dyn std::error::Error>> {
+    match config.db_type.as_str() {
+        "mongodb" => MongoTokenRepo::new(&config).await,
+        _ => panic!("Unsupported database type: {}", config.db_type),
+    }
+}
+
+/// Initializes the authentication audit log based on the provided database configuration.
+///
+/// # Arguments
+/// * `config` - The `DbConfig` containing database type and connection details.
+///
+/// # Returns
+/// * An instance of a type implementing `AuditRepository`.
+///
+/// # Panics
+/// * If the database type is unsupported.
+pub async fn init_audit_repo(
+    config: DbConfig,
+) -> Result<impl AuditRepository, Box<dyn std::error::Error>> {
+    match config.db_type.as_str() {
+        "mongodb" => MongoAuditRepo::new(&config).await,
+        _ => panic!("Unsupported database type: {}", config.db_type),
+    }
+}
+
+/// Initializes a WebAuthn challenge store based on the provided database configuration.
+///
+/// # Arguments
+/// * `config` - The `DbConfig` whose `challenge_store` selects the backend.
+/// * `collection_name` - The collection used by the MongoDB backend.
+/// * `ttl` - How long a ceremony stays valid.
+///
+/// # Returns
+/// * A shared instance of a type implementing `ChallengeStore`.
+///
+/// # Panics
+/// * If the challenge store type is unsupported.
+pub async fn init_challenge_store<T>(
+    config: &DbConfig,
+    collection_name: &str,
+    ttl: Duration,
+) -> Result<Arc<dyn ChallengeStore<T>>, Box<dyn std::error::Error>>
+where
+    T: Serialize + DeserializeOwned + Unpin + Send + Sync + 'static,
+{
+    match config.challenge_store.as_str() {
+        "memory" => Ok(Arc::new(MemoryChallengeStore::new(ttl))),
+        "mongodb" => Ok(Arc::new(
+            MongoChallengeStore::new(config, collection_name, ttl).await?,
+        )),
+        _ => panic!("Unsupported challenge store: {}", config.challenge_store),
+    }
+}
+
+/// Initializes the rate limit bucket store based on the provided database configuration.
+///
+/// # Arguments
+/// * `config` - The `DbConfig` whose `rate_limit_store` selects the backend.
+///
+/// # Returns
+/// * A shared instance of a type implementing `RateLimitStore`.
+///
+/// # Panics
+/// * If the rate limit store type is unsupported.
+pub async fn init_rate_limit_store(
+    config: &DbConfig,
+) -> Result<Arc<dyn RateLimitStore>, Box<dyn std::error::Error>> {
+    match config.rate_limit_store.as_str() {
+        "memory" => Ok(Arc::new(MemoryRateLimitStore::new())),
+        "mongodb" => Ok(Arc::new(MongoRateLimitStore::new(config).await?)),
+        _ => panic!("Unsupported rate limit store: {}", config.rate_limit_store),
+    }
+}
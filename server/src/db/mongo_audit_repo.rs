@@ -0,0 +1,117 @@
+use crate::db::{audit_repository::AuditRepository, db_config::DbConfig};
+use crate::models::auth_event::{AuthEvent, AuthEventQuery, AuthEventType};
+
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{self, doc, Document},
+    options::{ClientOptions, FindOptions},
+    Client, Collection, IndexModel,
+};
+
+#[derive(Clone)]
+pub struct MongoAuditRepo {
+    collection: Collection<AuthEvent>,
+}
+
+impl MongoAuditRepo {
+    /// Creates a new `MongoAuditRepo` instance.
+    pub async fn new(config: &DbConfig) -> Result<Self, Box<dyn std::error::Error>> {
+        let client_options = ClientOptions::parse(&config.connection_string).await?;
+        let client = Client::with_options(client_options)?;
+        let database = client.database(&config.database_name);
+        let collection: Collection<AuthEvent> = database.collection("auth_events");
+
+        collection
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "user_id": 1, "occurred_at": -1 })
+                    .build(),
+                None,
+            )
+            .await?;
+        collection
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "occurred_at": -1 })
+                    .build(),
+                None,
+            )
+            .await?;
+
+        Ok(MongoAuditRepo { collection })
+    }
+
+    async fn find_newest(
+        &self,
+        filter: Document,
+        limit: i64,
+    ) -> Result<Vec<AuthEvent>, Box<dyn std::error::Error + Send + Sync>> {
+        let options = FindOptions::builder()
+            .sort(doc! { "occurred_at": -1 })
+            .limit(limit)
+            .build();
+        let cursor = self.collection.find(filter, options).await?;
+        Ok(cursor.try_collect().await?)
+    }
+}
+
+#[async_trait::async_trait]
+impl AuditRepository for MongoAuditRepo {
+    async fn record(
+        &self,
+        event: AuthEvent,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.collection.insert_one(&event, None).await?;
+        Ok(())
+    }
+
+    async fn sign_in_history(
+        &self,
+        user_id: &str,
+        limit: i64,
+    ) -> Result<Vec<AuthEvent>, Box<dyn std::error::Error + Send + Sync>> {
+        let sign_in_types = bson::to_bson(&[
+            AuthEventType::Login,
+            AuthEventType::CounterRegression,
+            AuthEventType::Recovery,
+        ])?;
+        let filter = doc! { "user_id": user_id, "event_type": { "$in": sign_in_types } };
+        self.find_newest(filter, limit).await
+    }
+
+    async fn query(
+        &self,
+        query: AuthEventQuery,
+        limit: i64,
+    ) -> Result<Vec<AuthEvent>, Box<dyn std::error::Error + Send + Sync>> {
+        let mut filter = Document::new();
+        if let Some(user_id) = query.user_id {
+            filter.insert("user_id", user_id);
+        }
+        if let Some(username) = query.username {
+            filter.insert("username", username);
+        }
+        if let Some(event_type) = query.event_type {
+            filter.insert("event_type", bson::to_bson(&event_type)?);
+        }
+        if let Some(outcome) = query.outcome {
+            filter.insert("outcome", bson::to_bson(&outcome)?);
+        }
+        if let Some(ip) = query.ip {
+            filter.insert("ip", ip);
+        }
+
+        let mut occurred_at = Document::new();
+        if let Some(since) = query.since {
+            occurred_at.insert("$gte", bson::DateTime::from_chrono(since));
+        }
+        if let Some(until) = query.until {
+            occurred_at.insert("$lt", bson::DateTime::from_chrono(until));
+        }
+        if !occurred_at.is_empty() {
+            filter.insert("occurred_at", occurred_at);
+        }
+
+        self.find_newest(filter, limit).await
+    }
+}
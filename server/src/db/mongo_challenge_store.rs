@@ -0,0 +1,136 @@
+use crate::db::{
+    challenge_store::{ChallengeStore, StateError},
+    db_config::DbConfig,
+};
+
+use mongodb::{
+    bson::{doc, DateTime},
+    options::{ClientOptions, IndexOptions},
+    Client, Collection, IndexModel,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime};
+use uuid::Uuid;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CeremonyDocument<T> {
+    #[serde(rename = "_id")]
+    ceremony_id: String,
+    state: T,
+    expires_at: DateTime,
+}
+
+/// Challenge store shared by every server instance through a MongoDB collection
+///
+/// A TTL index on `expires_at` lets MongoDB drop abandoned ceremonies on its own;
+/// `take` still checks the expiry because the TTL monitor only runs once a minute.
+pub struct MongoChallengeStore<T> {
+    collection: Collection<CeremonyDocument<T>>,
+    ttl: Duration,
+    swept: AtomicU64,
+}
+
+impl<T> MongoChallengeStore<T> {
+    /// Creates a new `MongoChallengeStore` backed by `collection_name`
+    pub async fn new(
+        config: &DbConfig,
+        collection_name: &str,
+        ttl: Duration,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let client_options = ClientOptions::parse(&config.connection_string).await?;
+        let client = Client::with_options(client_options)?;
+        let database = client.database(&config.database_name);
+        let collection = database.collection(collection_name);
+
+        let ttl_index = IndexModel::builder()
+            .keys(doc! { "expires_at": 1 })
+            .options(
+                IndexOptions::builder()
+                    .expire_after(Duration::from_secs(0))
+                    .build(),
+            )
+            .build();
+        collection.create_index(ttl_index, None).await?;
+
+        Ok(MongoChallengeStore {
+            collection,
+            ttl,
+            swept: AtomicU64::new(0),
+        })
+    }
+}
+
+fn storage_error(err: mongodb::error::Error) -> StateError {
+    StateError::Storage(err.to_string())
+}
+
+#[async_trait::async_trait]
+impl<T> ChallengeStore<T> for MongoChallengeStore<T>
+where
+    T: Serialize + DeserializeOwned + Unpin + Send + Sync,
+{
+    async fn insert(&self, state: T) -> Result<String, StateError> {
+        let ceremony_id = Uuid::new_v4().to_string();
+        let document = CeremonyDocument {
+            ceremony_id: ceremony_id.clone(),
+            state,
+            expires_at: DateTime::from_system_time(SystemTime::now() + self.ttl),
+        };
+
+        self.collection
+            .insert_one(document, None)
+            .await
+            .map_err(storage_error)?;
+
+        Ok(ceremony_id)
+    }
+
+    async fn take(&self, ceremony_id: &str) -> Result<T, StateError> {
+        let document = self
+            .collection
+            .find_one_and_delete(doc! { "_id": ceremony_id }, None)
+            .await
+            .map_err(storage_error)?
+            .ok_or(StateError::NotFound)?;
+
+        if document.expires_at <= DateTime::now() {
+            return Err(StateError::Expired);
+        }
+
+        Ok(document.state)
+    }
+
+    async fn remove(&self, ceremony_id: &str) -> Result<(), StateError> {
+        self.collection
+            .delete_one(doc! { "_id": ceremony_id }, None)
+            .await
+            .map_err(storage_error)?;
+        Ok(())
+    }
+
+    async fn cleanup_expired(&self) -> Result<usize, StateError> {
+        let result = self
+            .collection
+            .delete_many(doc! { "expires_at": { "$lte": DateTime::now() } }, None)
+            .await
+            .map_err(storage_error)?;
+
+        self.swept
+            .fetch_add(result.deleted_count, Ordering::Relaxed);
+        Ok(result.deleted_count as usize)
+    }
+
+    async fn pending(&self) -> Result<usize, StateError> {
+        let count = self
+            .collection
+            .count_documents(None, None)
+            .await
+            .map_err(storage_error)?;
+        Ok(count as usize)
+    }
+
+    fn swept_count(&self) -> u64 {
+        self.swept.load(Ordering::Relaxed)
+    }
+}
@@ -1,19 +1,24 @@
-use crate::db::mongo_user_repo::MongoUserRepo;
 use crate::db::{db_config::DbConfig, poll_repository::PollRepository};
-use crate::models::poll_models::{PollOption, PollStatus, VotingPoll, VotingPollInput};
+use crate::models::poll_models::{
+    Ballot, PollOption, PollStatus, TallyIncrement, VotingPoll, VotingPollInput,
+};
+use crate::models::user_models::{User, Votes};
 
 use chrono::Utc;
 use futures::TryStreamExt;
 use mongodb::{
-    bson::doc,
+    bson::{self, doc, Document},
     options::{ClientOptions, UpdateOptions},
-    Client, Collection,
+    Client, Collection, IndexModel,
 };
 
 #[derive(Clone)]
 pub struct MongoPollRepo {
     collection: Collection<VotingPoll>,
-    config: DbConfig, // Add this field
+    /// Individual ballots, kept apart from the polls so they never show up in poll payloads
+    ballots: Collection<Ballot>,
+    /// The users collection, for the poll and vote history kept on each user
+    users: Collection<User>,
 }
 
 impl MongoPollRepo {
@@ -22,14 +27,92 @@ impl MongoPollRepo {
         let client_options = ClientOptions::parse(&config.connection_string).await?;
         let client = Client::with_options(client_options)?;
         let database = client.database(&config.database_name);
-        let collection = database.collection("polls");
+        let collection: Collection<VotingPoll> = database.collection("polls");
+        let ballots: Collection<Ballot> = database.collection("ballots");
+        let users: Collection<User> = database.collection("users");
+
+        ballots
+            .create_index(
+                IndexModel::builder().keys(doc! { "poll_id": 1 }).build(),
+                None,
+            )
+            .await?;
+
+        // Older releases closed polls with a lowercase status
+        collection
+            .update_many(
+                doc! { "status": "closed" },
+                doc! { "$set": { "status": bson::to_bson(&PollStatus::Closed)? } },
+                None,
+            )
+            .await?;
 
         Ok(MongoPollRepo {
             collection,
-            config: config.clone(), // Initialize the config field
+            ballots,
+            users,
         })
     }
 
+    /// Records `poll_id` among the polls `username` created
+    async fn add_owned_poll(
+        &self,
+        username: &str,
+        poll_id: i64,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let filter = doc! { "user_name": username };
+        let update = doc! {
+            "$addToSet": { "owned_polls": poll_id }
+        };
+
+        let result = self.users.update_one(filter, update, None).await?;
+
+        if result.matched_count == 0 {
+            eprintln!("User {} not found to update owned polls.", username);
+            return Err(Box::new(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                "User not found",
+            )));
+        }
+
+        println!(
+            "User {} updated with new owned poll ID: {}",
+            username, poll_id
+        );
+        Ok(())
+    }
+
+    /// Appends a vote to `username`'s voting history
+    async fn add_vote_to_user(
+        &self,
+        username: &str,
+        poll_id: i64,
+        option_ids: Vec<i64>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let filter = doc! { "user_name": username };
+        let vote = Votes::new(poll_id, option_ids);
+
+        let update = doc! {
+            "$push": { "polls_voted": bson::to_bson(&vote)? }
+        };
+
+        let result = self.users.update_one(filter, update, None).await?;
+
+        if result.matched_count == 0 {
+            eprintln!("User {} not found to update polls voted.", username);
+            return Err(Box::new(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                "User not found",
+            )));
+        }
+
+        println!(
+            "User {} updated successfully with vote: Poll ID {}, Option IDs {:?}",
+            username, poll_id, vote.option_ids
+        );
+        Ok(())
+    }
+
     async fn get_next_poll_id(&self) -> Result<i64, Box<dyn std::error::Error>> {
         // Find the highest poll_id and increment it
         let options = mongodb::options::FindOptions::builder()
@@ -59,6 +142,7 @@ impl PollRepository for MongoPollRepo {
     async fn create_poll(
         &self,
         poll_input: VotingPollInput,
+        creator: String,
     ) -> Result<VotingPoll, Box<dyn std::error::Error>> {
         println!("Creating Poll from input: {:#?}", poll_input);
 
@@ -74,6 +158,8 @@ impl PollRepository for MongoPollRepo {
                 option_id: (index + 1) as i64,
                 text: option.text,
                 votes: 0,
+                voters: Some(0),
+                score_counts: poll_input.voting_method.empty_score_counts(),
             })
             .collect();
 
@@ -81,13 +167,15 @@ impl PollRepository for MongoPollRepo {
         let poll = VotingPoll {
             poll_id: Some(next_id),
             title: poll_input.title,
-            creator: poll_input.creator.clone(),
+            creator: creator.clone(),
             description: poll_input.description,
             created_at: Utc::now(),
             expiration_date: poll_input.expiration_date,
             status: PollStatus::Active,
             options,
             users_voted: Vec::new(),
+            voting_method: poll_input.voting_method,
+            eligible_voters: poll_input.eligible_voters,
         };
 
         // Insert the new poll
@@ -99,10 +187,7 @@ impl PollRepository for MongoPollRepo {
         println!("Poll created successfully with ID: {}", next_id);
 
         // Update the creator's owned_polls
-        let user_repo = MongoUserRepo::new(&self.config).await?;
-        user_repo
-            .add_owned_poll(&poll_input.creator, next_id)
-            .await?;
+        self.add_owned_poll(&creator, next_id).await?;
 
         Ok(poll)
     }
@@ -136,32 +221,105 @@ impl PollRepository for MongoPollRepo {
     ) -> Result<(), Box<dyn std::error::Error>> {
         let filter = doc! { "poll_id": poll_id };
         let update = match target.as_str() {
-            "reset" => doc! { "$set": { "options.$[].votes": 0 } },
-            "close" => doc! { "$set": { "status": "closed" } },
+            "reset" => {
+                // Scored polls also keep a histogram per option, whose length depends on the poll
+                let score_counts = self
+                    .collection
+                    .find_one(filter.clone(), None)
+                    .await?
+                    .map(|poll| poll.voting_method.empty_score_counts())
+                    .unwrap_or_default();
+                let mut reset = doc! { "options.$[].votes": 0, "options.$[].voters": 0 };
+                if !score_counts.is_empty() {
+                    reset.insert("options.$[].score_counts", score_counts);
+                }
+                doc! { "$set": reset }
+            }
+            "close" => doc! { "$set": { "status": bson::to_bson(&PollStatus::Closed)? } },
             _ => {
                 return Err(format!("Invalid target: {}", target).into());
             }
         };
 
         self.collection.update_one(filter, update, None).await?;
+        if target == "reset" {
+            self.ballots
+                .delete_many(doc! { "poll_id": poll_id }, None)
+                .await?;
+        }
         println!("Poll with ID {} updated successfully.", poll_id);
         Ok(())
     }
 
     async fn delete_poll(&self, poll_id: i64) -> Result<(), Box<dyn std::error::Error>> {
         let filter = doc! { "poll_id": poll_id };
-        self.collection.delete_one(filter, None).await?;
+        self.collection.delete_one(filter.clone(), None).await?;
+        self.ballots.delete_many(filter, None).await?;
         println!("Poll with ID {} deleted successfully.", poll_id);
         Ok(())
     }
 
-    async fn vote_poll(
+    async fn get_polls(
+        &self,
+        poll_ids: Vec<i64>,
+    ) -> Result<Vec<VotingPoll>, Box<dyn std::error::Error + Send + Sync>> {
+        let filter = doc! { "poll_id": { "$in": poll_ids } };
+        let cursor = self.collection.find(filter, None).await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    async fn reassign_polls(
+        &self,
+        poll_ids: Vec<i64>,
+        creator: String,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let filter = doc! { "poll_id": { "$in": poll_ids } };
+        let update = doc! { "$set": { "creator": &creator } };
+        let result = self.collection.update_many(filter, update, None).await?;
+        println!("Reassigned {} polls to {}.", result.modified_count, creator);
+        Ok(())
+    }
+
+    async fn delete_polls(
+        &self,
+        poll_ids: Vec<i64>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let filter = doc! { "poll_id": { "$in": poll_ids } };
+        let result = self.collection.delete_many(filter.clone(), None).await?;
+        self.ballots.delete_many(filter, None).await?;
+        println!("Deleted {} polls.", result.deleted_count);
+        Ok(())
+    }
+
+    async fn anonymize_voter(
+        &self,
+        username: String,
+        tombstone: String,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        // Only the voter list changes; the ballots already counted in `options` stay
+        let filter = doc! { "users_voted": &username };
+        let update = doc! { "$set": { "users_voted.$": &tombstone } };
+        self.collection.update_many(filter, update, None).await?;
+        Ok(())
+    }
+
+    async fn get_ballots(
         &self,
         poll_id: i64,
-        option_id: i64,
+    ) -> Result<Vec<Ballot>, Box<dyn std::error::Error + Send + Sync>> {
+        let filter = doc! { "poll_id": poll_id };
+        let cursor = self.ballots.find(filter, None).await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    async fn vote_poll(
+        &self,
+        ballot: Ballot,
+        increments: Vec<TallyIncrement>,
         username: String,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        println!("Recording vote for option {}", option_id);
+        let poll_id = ballot.poll_id;
+        println!("Recording vote for options {:?}", ballot.option_ids);
 
         let filter = doc! {
             "poll_id": poll_id,
@@ -169,19 +327,57 @@ impl PollRepository for MongoPollRepo {
             "users_voted": { "$ne": &username } // Ensures the user hasn't already voted
         };
 
+        // Each tallied option gets its own array filter, as the amounts differ.
+        // Voter counts are only kept on options that already have one, since
+        // counting from zero partway through a poll would understate them.
+        let mut inc = Document::new();
+        let mut array_filters = Vec::new();
+        for (index, increment) in increments.iter().enumerate() {
+            let elem = format!("elem{}", index);
+            inc.insert(format!("options.$[{}].votes", elem), increment.votes);
+            if let Some(score) = increment.score {
+                inc.insert(
+                    format!("options.$[{}].score_counts.{}", elem, score),
+                    increment.weight,
+                );
+            }
+            array_filters.push(doc! { format!("{}.option_id", elem): increment.option_id });
+
+            let counted = format!("counted{}", index);
+            inc.insert(format!("options.$[{}].voters", counted), 1);
+            array_filters.push(doc! {
+                format!("{}.option_id", counted): increment.option_id,
+                format!("{}.voters", counted): { "$exists": true },
+            });
+        }
+
         let update = doc! {
-            "$inc": { "options.$[elem].votes": 1 }, // Increment the vote count for the selected option
+            "$inc": inc,
             "$push": { "users_voted": &username } // Add the username to the list of users who voted
         };
 
-        let array_filters = vec![doc! { "elem.option_id": option_id }];
         let options = UpdateOptions::builder()
             .array_filters(array_filters)
             .build();
 
-        let result = self.collection.update_one(filter, update, options).await?;
+        // The ballot goes in first so a counted vote always has one; it is
+        // removed again if the vote can't be counted
+        let ballot_id = self.ballots.insert_one(&ballot, None).await?.inserted_id;
+
+        let result = match self.collection.update_one(filter, update, options).await {
+            Ok(result) => result,
+            Err(e) => {
+                self.ballots
+                    .delete_one(doc! { "_id": ballot_id }, None)
+                    .await?;
+                return Err(Box::new(e));
+            }
+        };
 
         if result.matched_count == 0 {
+            self.ballots
+                .delete_one(doc! { "_id": ballot_id }, None)
+                .await?;
             eprintln!("No matching poll found or user has already voted.");
             return Err(Box::new(std::io::Error::new(
                 std::io::ErrorKind::PermissionDenied,
@@ -192,9 +388,7 @@ impl PollRepository for MongoPollRepo {
         println!("Vote recorded successfully for poll ID {}.", poll_id);
 
         // Update the user's polls_voted field
-        let user_repo = MongoUserRepo::new(&self.config).await?;
-        user_repo
-            .add_vote_to_user(&username, poll_id, option_id)
+        self.add_vote_to_user(&username, poll_id, ballot.option_ids)
             .await?;
 
         Ok(())
@@ -0,0 +1,108 @@
+use crate::db::{
+    db_config::DbConfig,
+    rate_limit_store::{RateDecision, RateLimitPolicy, RateLimitStore},
+};
+
+use mongodb::{
+    bson::{doc, DateTime, Document},
+    options::{ClientOptions, FindOneAndUpdateOptions, IndexOptions, ReturnDocument},
+    Client, Collection, IndexModel,
+};
+use std::time::Duration;
+
+/// Rate limit buckets shared by every server instance through a MongoDB collection
+///
+/// Each bucket is refilled and drawn from in a single pipeline update, so
+/// concurrent requests on different instances never overspend it.
+pub struct MongoRateLimitStore {
+    collection: Collection<Document>,
+}
+
+impl MongoRateLimitStore {
+    /// Creates a new `MongoRateLimitStore` instance.
+    pub async fn new(config: &DbConfig) -> Result<Self, Box<dyn std::error::Error>> {
+        let client_options = ClientOptions::parse(&config.connection_string).await?;
+        let client = Client::with_options(client_options)?;
+        let database = client.database(&config.database_name);
+        let collection: Collection<Document> = database.collection("rate_limits");
+
+        // Buckets that have refilled completely are dropped by MongoDB on its own
+        collection
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "expires_at": 1 })
+                    .options(
+                        IndexOptions::builder()
+                            .expire_after(Duration::from_secs(0))
+                            .build(),
+                    )
+                    .build(),
+                None,
+            )
+            .await?;
+
+        Ok(MongoRateLimitStore { collection })
+    }
+}
+
+#[async_trait::async_trait]
+impl RateLimitStore for MongoRateLimitStore {
+    async fn acquire(
+        &self,
+        key: &str,
+        policy: &RateLimitPolicy,
+    ) -> Result<RateDecision, Box<dyn std::error::Error + Send + Sync>> {
+        let now = DateTime::now();
+        let capacity = policy.capacity as f64;
+        let expires_at =
+            DateTime::from_millis(now.timestamp_millis() + policy.full_refill().as_millis() as i64);
+
+        let pipeline = vec![
+            // Refill for the time since the last request, starting new buckets full
+            doc! { "$set": {
+                "tokens": { "$min": [
+                    capacity,
+                    { "$add": [
+                        { "$ifNull": ["$tokens", capacity] },
+                        { "$multiply": [
+                            { "$divide": [
+                                { "$subtract": [now, { "$ifNull": ["$updated_at", now] }] },
+                                1000,
+                            ] },
+                            policy.refill_rate(),
+                        ] },
+                    ] },
+                ] },
+                "updated_at": now,
+                "expires_at": expires_at,
+            } },
+            // Both fields see the refilled count from the previous stage
+            doc! { "$set": {
+                "allowed": { "$gte": ["$tokens", 1] },
+                "tokens": { "$cond": [
+                    { "$gte": ["$tokens", 1] },
+                    { "$subtract": ["$tokens", 1] },
+                    "$tokens",
+                ] },
+            } },
+        ];
+        let options = FindOneAndUpdateOptions::builder()
+            .upsert(true)
+            .return_document(ReturnDocument::After)
+            .build();
+
+        let bucket = self
+            .collection
+            .find_one_and_update(doc! { "_id": key }, pipeline, options)
+            .await?
+            .ok_or("Rate limit bucket was not upserted")?;
+
+        if bucket.get_bool("allowed")? {
+            Ok(RateDecision::Allowed)
+        } else {
+            Ok(RateDecision::Limited {
+                retry_after: policy.retry_after(bucket.get_f64("tokens")?),
+            })
+        }
+    }
+}
@@ -0,0 +1,230 @@
+use crate::db::{db_config::DbConfig, token_repository::TokenRepository};
+use crate::models::api_token_models::ApiToken;
+use crate::models::auth_jwt::Claims;
+use crate::models::token_models::{RefreshToken, RevokedToken, TokenCutoff};
+
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, DateTime},
+    options::{ClientOptions, IndexOptions, UpdateOptions},
+    Client, Collection, IndexModel,
+};
+use std::time::Duration;
+
+#[derive(Clone)]
+pub struct MongoTokenRepo {
+    refresh_tokens: Collection<RefreshToken>,
+    revoked_tokens: Collection<RevokedToken>,
+    token_cutoffs: Collection<TokenCutoff>,
+    api_tokens: Collection<ApiToken>,
+}
+
+impl MongoTokenRepo {
+    /// Creates a new `MongoTokenRepo` instance.
+    pub async fn new(config: &DbConfig) -> Result<Self, Box<dyn std::error::Error>> {
+        let client_options = ClientOptions::parse(&config.connection_string).await?;
+        let client = Client::with_options(client_options)?;
+        let database = client.database(&config.database_name);
+
+        let refresh_tokens: Collection<RefreshToken> = database.collection("refresh_tokens");
+        let revoked_tokens: Collection<RevokedToken> = database.collection("revoked_tokens");
+        let token_cutoffs = database.collection("token_cutoffs");
+        let api_tokens: Collection<ApiToken> = database.collection("api_tokens");
+
+        // Expired tokens are dropped by MongoDB on its own
+        refresh_tokens.create_index(Self::ttl_index(), None).await?;
+        revoked_tokens.create_index(Self::ttl_index(), None).await?;
+        api_tokens.create_index(Self::ttl_index(), None).await?;
+        refresh_tokens
+            .create_index(Self::token_hash_index(), None)
+            .await?;
+        api_tokens
+            .create_index(Self::token_hash_index(), None)
+            .await?;
+
+        Ok(MongoTokenRepo {
+            refresh_tokens,
+            revoked_tokens,
+            token_cutoffs,
+            api_tokens,
+        })
+    }
+
+    fn token_hash_index() -> IndexModel {
+        IndexModel::builder()
+            .keys(doc! { "token_hash": 1 })
+            .options(IndexOptions::builder().unique(true).build())
+            .build()
+    }
+
+    fn ttl_index() -> IndexModel {
+        IndexModel::builder()
+            .keys(doc! { "expires_at": 1 })
+            .options(
+                IndexOptions::builder()
+                    .expire_after(Duration::from_secs(0))
+                    .build(),
+            )
+            .build()
+    }
+}
+
+#[async_trait::async_trait]
+impl TokenRepository for MongoTokenRepo {
+    async fn store_refresh_token(
+        &self,
+        token: RefreshToken,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.refresh_tokens.insert_one(&token, None).await?;
+        Ok(())
+    }
+
+    async fn find_refresh_token(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<RefreshToken>, Box<dyn std::error::Error + Send + Sync>> {
+        let filter = doc! { "token_hash": token_hash };
+        Ok(self.refresh_tokens.find_one(filter, None).await?)
+    }
+
+    async fn consume_refresh_token(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<RefreshToken>, Box<dyn std::error::Error + Send + Sync>> {
+        let filter = doc! {
+            "token_hash": token_hash,
+            "used": false,
+            "revoked": false,
+            "expires_at": { "$gt": DateTime::now() }
+        };
+        let update = doc! { "$set": { "used": true } };
+
+        Ok(self
+            .refresh_tokens
+            .find_one_and_update(filter, update, None)
+            .await?)
+    }
+
+    async fn revoke_refresh_family(
+        &self,
+        family_id: &str,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let filter = doc! { "family_id": family_id };
+        let update = doc! { "$set": { "revoked": true } };
+        self.refresh_tokens
+            .update_many(filter, update, None)
+            .await?;
+        Ok(())
+    }
+
+    async fn revoke_user_refresh_tokens(
+        &self,
+        user_id: &str,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let filter = doc! { "user_id": user_id };
+        let update = doc! { "$set": { "revoked": true } };
+        self.refresh_tokens
+            .update_many(filter, update, None)
+            .await?;
+        Ok(())
+    }
+
+    async fn revoke_access_token(
+        &self,
+        claims: &Claims,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let revoked = RevokedToken {
+            jti: claims.jti.clone(),
+            user_id: claims.uuid.to_string(),
+            expires_at: DateTime::from_millis(claims.exp as i64 * 1000),
+        };
+        self.revoked_tokens.insert_one(&revoked, None).await?;
+        Ok(())
+    }
+
+    async fn revoke_all_access_tokens(
+        &self,
+        user_id: &str,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        // `iat` has second precision, so the cutoff does too
+        let now_secs = DateTime::now().timestamp_millis() / 1000;
+        let filter = doc! { "user_id": user_id };
+        let update = doc! {
+            "$set": { "not_before": DateTime::from_millis(now_secs * 1000) }
+        };
+        let options = UpdateOptions::builder().upsert(true).build();
+
+        self.token_cutoffs
+            .update_one(filter, update, options)
+            .await?;
+        Ok(())
+    }
+
+    async fn is_access_token_revoked(
+        &self,
+        claims: &Claims,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        let filter = doc! { "jti": &claims.jti };
+        if self.revoked_tokens.count_documents(filter, None).await? > 0 {
+            return Ok(true);
+        }
+
+        let filter = doc! { "user_id": claims.uuid.to_string() };
+        let issued_at = DateTime::from_millis(claims.iat as i64 * 1000);
+        Ok(self
+            .token_cutoffs
+            .find_one(filter, None)
+            .await?
+            .is_some_and(|cutoff| issued_at < cutoff.not_before))
+    }
+
+    async fn store_api_token(
+        &self,
+        token: ApiToken,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.api_tokens.insert_one(&token, None).await?;
+        Ok(())
+    }
+
+    async fn use_api_token(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<ApiToken>, Box<dyn std::error::Error + Send + Sync>> {
+        let now = DateTime::now();
+        let filter = doc! { "token_hash": token_hash, "expires_at": { "$gt": now } };
+        let update = doc! { "$set": { "last_used_at": now } };
+
+        Ok(self
+            .api_tokens
+            .find_one_and_update(filter, update, None)
+            .await?)
+    }
+
+    async fn list_api_tokens(
+        &self,
+        user_id: &str,
+    ) -> Result<Vec<ApiToken>, Box<dyn std::error::Error + Send + Sync>> {
+        let filter = doc! { "user_id": user_id, "expires_at": { "$gt": DateTime::now() } };
+        let cursor = self.api_tokens.find(filter, None).await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    async fn revoke_api_token(
+        &self,
+        user_id: &str,
+        token_id: &str,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        let filter = doc! { "user_id": user_id, "token_id": token_id };
+        let result = self.api_tokens.delete_one(filter, None).await?;
+        Ok(result.deleted_count > 0)
+    }
+
+    async fn revoke_user_api_tokens(
+        &self,
+        user_id: &str,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let filter = doc! { "user_id": user_id };
+        self.api_tokens.delete_many(filter, None).await?;
+        Ok(())
+    }
+}
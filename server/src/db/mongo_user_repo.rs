@@ -1,80 +1,212 @@
 use crate::db::{db_config::DbConfig, user_repository::UserRepository};
-use crate::models::user_models::{User, Votes};
+use crate::models::profile_models::{Avatar, Profile};
+use crate::models::user_models::{normalize_username, CredentialInfo, User, USERNAME_MAX_LEN};
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt;
+use std::collections::{HashMap, HashSet};
+use webauthn_rs::prelude::Passkey;
 
-use mongodb::bson::{self, doc};
-use mongodb::{options::ClientOptions, Client, Collection};
+use mongodb::bson::{self, doc, Document};
+use mongodb::{
+    options::{
+        ClientOptions, FindOneAndUpdateOptions, FindOptions, IndexOptions, ReturnDocument,
+        UpdateOptions,
+    },
+    Client, Collection, Database, IndexModel,
+};
 
 #[derive(Clone)]
 pub struct MongoUserRepo {
     collection: Collection<User>,
+    /// Usernames of deleted accounts, which may not be registered again
+    reserved_usernames: Collection<Document>,
 }
 
 impl MongoUserRepo {
-    /// Creates a new `MongoUserRepo` instance.
-    pub async fn new(config: &DbConfig) -> Result<Self, Box<dyn std::error::Error>> {
-        let client_options = ClientOptions::parse(&config.connection_string).await?;
-        let client = Client::with_options(client_options)?;
-        let database = client.database(&config.database_name);
-        let collection = database.collection("users");
+    /// Gives a user stored before passkey metadata existed a `credentials`
+    /// entry for each of their passkeys
+    async fn backfill_credentials(
+        &self,
+        user_id: &str,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let filter = doc! { "user_id": user_id, "credentials": { "$exists": false } };
+        let Some(user) = self.collection.find_one(filter.clone(), None).await? else {
+            return Ok(());
+        };
 
-        Ok(MongoUserRepo { collection })
+        let credentials: Vec<CredentialInfo> = user
+            .keys
+            .iter()
+            .map(|passkey| CredentialInfo {
+                credential_id: passkey.cred_id().to_string(),
+                nickname: None,
+                created_at: None,
+                last_used_at: None,
+            })
+            .collect();
+        // Filtering on the missing array again keeps a concurrent backfill from being overwritten
+        let update = doc! { "$set": { "credentials": bson::to_bson(&credentials)? } };
+        self.collection.update_one(filter, update, None).await?;
+        Ok(())
     }
 
-    pub async fn add_owned_poll(
-        &self,
-        username: &str,
-        poll_id: i64,
+    /// Brings usernames stored before names were normalized into their normalized
+    /// form, so they can be looked up and kept unique as typed at sign-in
+    ///
+    /// Names already in normalized form keep it. A legacy name whose normalized form
+    /// is taken, or an exact duplicate, gets the first free `-2`, `-3`, ... suffix,
+    /// the oldest account being renamed first. The polls created and voted in are
+    /// renamed along with the account.
+    async fn normalize_stored_usernames(
+        database: &Database,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        let filter = doc! { "user_name": username };
-        let update = doc! {
-            "$addToSet": { "owned_polls": poll_id }
-        };
+        let users: Collection<Document> = database.collection("users");
+        let polls: Collection<Document> = database.collection("polls");
+        let reserved_usernames: Collection<Document> = database.collection("reserved_usernames");
 
-        let result = self.collection.update_one(filter, update, None).await?;
+        // Reserved names are matched against normalized names from now on too
+        let mut claimed = HashSet::new();
+        let reserved: Vec<Document> = reserved_usernames
+            .find(None, None)
+            .await?
+            .try_collect()
+            .await?;
+        for entry in reserved {
+            let Ok(user_name) = entry.get_str("user_name") else {
+                continue;
+            };
+            let normalized =
+                normalize_username(user_name).unwrap_or_else(|_| user_name.to_string());
+            if normalized != user_name {
+                let id = entry.get("_id").cloned().unwrap_or(bson::Bson::Null);
+                if claimed.contains(&normalized)
+                    || reserved_usernames
+                        .count_documents(doc! { "user_name": &normalized }, None)
+                        .await?
+                        > 0
+                {
+                    reserved_usernames
+                        .delete_one(doc! { "_id": id }, None)
+                        .await?;
+                } else {
+                    reserved_usernames
+                        .update_one(
+                            doc! { "_id": id },
+                            doc! { "$set": { "user_name": &normalized } },
+                            None,
+                        )
+                        .await?;
+                }
+            }
+            claimed.insert(normalized);
+        }
 
-        if result.matched_count == 0 {
-            eprintln!("User {} not found to update owned polls.", username);
-            return Err(Box::new(std::io::Error::new(
-                std::io::ErrorKind::NotFound,
-                "User not found",
-            )));
+        let options = FindOptions::builder()
+            .projection(doc! { "user_name": 1 })
+            .sort(doc! { "_id": 1 })
+            .build();
+        let accounts: Vec<Document> = users.find(None, options).await?.try_collect().await?;
+        let accounts: Vec<(bson::Bson, String, String)> = accounts
+            .into_iter()
+            .filter_map(|account| {
+                let user_name = account.get_str("user_name").ok()?.to_string();
+                let normalized =
+                    normalize_username(&user_name).unwrap_or_else(|_| user_name.clone());
+                Some((account.get("_id")?.clone(), user_name, normalized))
+            })
+            .collect();
+
+        // Names already normalized are the ones users sign in with today, so they are claimed first
+        let mut renames = Vec::new();
+        let (current, legacy): (Vec<_>, Vec<_>) = accounts
+            .into_iter()
+            .partition(|(_, user_name, normalized)| user_name == normalized);
+        for (id, user_name, normalized) in current.into_iter().chain(legacy) {
+            let mut target = normalized.clone();
+            let mut suffix = 1;
+            while claimed.contains(&target) {
+                suffix += 1;
+                let suffix = format!("-{}", suffix);
+                let base: String = normalized
+                    .chars()
+                    .take(USERNAME_MAX_LEN - suffix.len())
+                    .collect();
+                target = format!("{}{}", base, suffix);
+            }
+            claimed.insert(target.clone());
+            if target != user_name {
+                renames.push((id, user_name, target));
+            }
         }
 
-        println!(
-            "User {} updated with new owned poll ID: {}",
-            username, poll_id
-        );
+        for (id, user_name, target) in renames {
+            if target.to_lowercase() != user_name.to_lowercase() {
+                eprintln!(
+                    "Username {} is taken once normalized; renaming the account to {}",
+                    user_name, target
+                );
+            }
+            users
+                .update_one(
+                    doc! { "_id": id },
+                    doc! { "$set": { "user_name": &target } },
+                    None,
+                )
+                .await?;
+            polls
+                .update_many(
+                    doc! { "creator": &user_name },
+                    doc! { "$set": { "creator": &target } },
+                    None,
+                )
+                .await?;
+            polls
+                .update_many(
+                    doc! { "users_voted": &user_name },
+                    doc! { "$set": { "users_voted.$": &target } },
+                    None,
+                )
+                .await?;
+        }
         Ok(())
     }
 
-    pub async fn add_vote_to_user(
-        &self,
-        username: &str,
-        poll_id: i64,
-        option_id: i64,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        let filter = doc! { "user_name": username };
-        let vote = Votes { poll_id, option_id };
+    /// Creates a new `MongoUserRepo` instance.
+    pub async fn new(config: &DbConfig) -> Result<Self, Box<dyn std::error::Error>> {
+        let client_options = ClientOptions::parse(&config.connection_string).await?;
+        let client = Client::with_options(client_options)?;
+        let database = client.database(&config.database_name);
+        let collection: Collection<User> = database.collection("users");
 
-        let update = doc! {
-            "$push": { "polls_voted": bson::to_bson(&vote)? }
-        };
+        // Legacy names must be normalized and de-duplicated before the unique index can hold
+        Self::normalize_stored_usernames(&database).await?;
 
-        let result = self.collection.update_one(filter, update, None).await?;
+        // Usernames identify accounts, so two users may never share one
+        collection
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "user_name": 1 })
+                    .options(IndexOptions::builder().unique(true).build())
+                    .build(),
+                None,
+            )
+            .await?;
 
-        if result.matched_count == 0 {
-            eprintln!("User {} not found to update polls voted.", username);
-            return Err(Box::new(std::io::Error::new(
-                std::io::ErrorKind::NotFound,
-                "User not found",
-            )));
-        }
+        let reserved_usernames: Collection<Document> = database.collection("reserved_usernames");
+        reserved_usernames
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "user_name": 1 })
+                    .options(IndexOptions::builder().unique(true).build())
+                    .build(),
+                None,
+            )
+            .await?;
 
-        println!(
-            "User {} updated successfully with vote: Poll ID {}, Option ID {}",
-            username, poll_id, option_id
-        );
-        Ok(())
+        Ok(MongoUserRepo {
+            collection,
+            reserved_usernames,
+        })
     }
 }
 
@@ -112,40 +244,295 @@ impl UserRepository for MongoUserRepo {
         }
     }
 
-    async fn update_user(
+    async fn get_user_by_id(
         &self,
-        user_name: String,
-        vote: Votes,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        // Define the filter to find the user by their username
-        let filter = doc! { "user_name": &user_name };
+        user_id: String,
+    ) -> Result<Option<User>, Box<dyn std::error::Error + Send + Sync>> {
+        let filter = doc! { "user_id": user_id.clone() };
+
+        match self.collection.find_one(filter, None).await {
+            Ok(user) => {
+                if user.is_none() {
+                    eprintln!("No user found with ID: {}", user_id);
+                }
+                Ok(user)
+            }
+            Err(e) => {
+                eprintln!("Error retrieving user: {:?}", e);
+                Err(Box::new(e))
+            }
+        }
+    }
+
+    async fn get_user_by_credential(
+        &self,
+        credential_id: String,
+    ) -> Result<Option<User>, Box<dyn std::error::Error + Send + Sync>> {
+        let filter = doc! { "keys.cred.cred_id": credential_id.clone() };
 
-        // Use `$push` with `$ifNull` to append the vote or initialize the array
+        match self.collection.find_one(filter, None).await {
+            Ok(user) => {
+                if user.is_none() {
+                    eprintln!("No user found with credential: {}", credential_id);
+                }
+                Ok(user)
+            }
+            Err(e) => {
+                eprintln!("Error retrieving user: {:?}", e);
+                Err(Box::new(e))
+            }
+        }
+    }
+
+    async fn add_passkey(
+        &self,
+        user_id: String,
+        passkey: Passkey,
+        info: CredentialInfo,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let filter = doc! { "user_id": &user_id };
         let update = doc! {
             "$push": {
-                "polls_voted": {
-                    "$each": [bson::to_bson(&vote)?],
-                    "$position": 0 // Optional: Add to the start of the array (most recent first)
-                }
+                "keys": bson::to_bson(&passkey)?,
+                "credentials": bson::to_bson(&info)?,
             }
         };
 
-        // Update the user document
         let result = self.collection.update_one(filter, update, None).await?;
 
-        // Check if the update was successful
-        if result.modified_count == 0 {
-            eprintln!("Failed to update user: {}", user_name);
+        if result.matched_count == 0 {
+            eprintln!("User {} not found to add passkey.", user_id);
             return Err(Box::new(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                "Failed to update user's voting history",
+                std::io::ErrorKind::NotFound,
+                "User not found",
             )));
         }
 
-        println!(
-            "User '{}' updated successfully with vote: {:?}",
-            user_name, vote
-        );
+        println!("Added passkey {} to user {}", info.credential_id, user_id);
+        Ok(())
+    }
+
+    async fn set_recovery_codes(
+        &self,
+        user_id: String,
+        code_hashes: Vec<String>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let filter = doc! { "user_id": &user_id };
+        let update = doc! { "$set": { "recovery_codes": code_hashes } };
+
+        let result = self.collection.update_one(filter, update, None).await?;
+
+        if result.matched_count == 0 {
+            eprintln!("User {} not found to set recovery codes.", user_id);
+            return Err(Box::new(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                "User not found",
+            )));
+        }
+
+        Ok(())
+    }
+
+    async fn consume_recovery_code(
+        &self,
+        user_id: String,
+        code_hash: String,
+    ) -> Result<Option<usize>, Box<dyn std::error::Error + Send + Sync>> {
+        // Matching on the code in the filter makes each code usable exactly once
+        let filter = doc! { "user_id": &user_id, "recovery_codes": &code_hash };
+        let update = doc! { "$pull": { "recovery_codes": &code_hash } };
+        let options = FindOneAndUpdateOptions::builder()
+            .return_document(ReturnDocument::After)
+            .build();
+
+        Ok(self
+            .collection
+            .find_one_and_update(filter, update, options)
+            .await?
+            .map(|user| user.recovery_codes.len()))
+    }
+
+    async fn update_credential(
+        &self,
+        user_id: String,
+        credential_id: String,
+        passkey: Option<Passkey>,
+        last_used_at: DateTime<Utc>,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        // The filtered update below fails outright on a document without the array
+        self.backfill_credentials(&user_id).await?;
+
+        let filter = doc! { "user_id": &user_id, "keys.cred.cred_id": &credential_id };
+
+        let mut set = doc! { "credentials.$[c].last_used_at": bson::to_bson(&last_used_at)? };
+        if let Some(passkey) = passkey {
+            set.insert("keys.$[k]", bson::to_bson(&passkey)?);
+        }
+
+        let options = UpdateOptions::builder()
+            .array_filters(vec![
+                doc! { "k.cred.cred_id": &credential_id },
+                doc! { "c.credential_id": &credential_id },
+            ])
+            .build();
+
+        let result = self
+            .collection
+            .update_one(filter, doc! { "$set": set }, options)
+            .await?;
+
+        if result.matched_count == 0 {
+            return Ok(false);
+        }
+
+        // Passkeys registered before metadata was tracked get an entry on first use
+        let filter = doc! {
+            "user_id": &user_id,
+            "credentials.credential_id": { "$ne": &credential_id },
+        };
+        let info = CredentialInfo {
+            credential_id,
+            nickname: None,
+            created_at: None,
+            last_used_at: Some(last_used_at),
+        };
+        let update = doc! { "$push": { "credentials": bson::to_bson(&info)? } };
+        self.collection.update_one(filter, update, None).await?;
+
+        Ok(true)
+    }
+
+    async fn rename_passkey(
+        &self,
+        user_id: String,
+        credential_id: String,
+        nickname: String,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        let filter = doc! { "user_id": &user_id, "credentials.credential_id": &credential_id };
+        let update = doc! { "$set": { "credentials.$.nickname": &nickname } };
+
+        let result = self.collection.update_one(filter, update, None).await?;
+        if result.matched_count > 0 {
+            return Ok(true);
+        }
+
+        // Passkeys registered before metadata was tracked get an entry on first rename
+        let filter = doc! { "user_id": &user_id, "keys.cred.cred_id": &credential_id };
+        let info = CredentialInfo {
+            credential_id,
+            nickname: Some(nickname),
+            created_at: None,
+            last_used_at: None,
+        };
+        let update = doc! { "$push": { "credentials": bson::to_bson(&info)? } };
+
+        let result = self.collection.update_one(filter, update, None).await?;
+        Ok(result.matched_count > 0)
+    }
+
+    async fn remove_passkey(
+        &self,
+        user_id: String,
+        credential_id: String,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        // Matching on `keys.1` makes the last-passkey guard part of the same atomic update
+        let filter = doc! {
+            "user_id": &user_id,
+            "keys.cred.cred_id": &credential_id,
+            "keys.1": { "$exists": true },
+        };
+        let update = doc! {
+            "$pull": {
+                "keys": { "cred.cred_id": &credential_id },
+                "credentials": { "credential_id": &credential_id },
+            }
+        };
+
+        let result = self.collection.update_one(filter, update, None).await?;
+        Ok(result.modified_count > 0)
+    }
+
+    async fn update_profile(
+        &self,
+        user_id: String,
+        profile: Profile,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        // Field by field, so a concurrent avatar upload is not overwritten
+        let mut fields = bson::to_document(&profile)?;
+        fields.remove("avatar");
+        let fields: Document = fields
+            .into_iter()
+            .map(|(key, value)| (format!("profile.{}", key), value))
+            .collect();
+
+        let filter = doc! { "user_id": &user_id };
+        let update = doc! { "$set": fields };
+
+        let result = self.collection.update_one(filter, update, None).await?;
+        Ok(result.matched_count > 0)
+    }
+
+    async fn set_avatar(
+        &self,
+        user_id: String,
+        avatar: Option<Avatar>,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        let filter = doc! { "user_id": &user_id };
+        let update = doc! { "$set": { "profile.avatar": bson::to_bson(&avatar)? } };
+
+        let result = self.collection.update_one(filter, update, None).await?;
+        Ok(result.matched_count > 0)
+    }
+
+    async fn display_names(
+        &self,
+        user_names: Vec<String>,
+    ) -> Result<HashMap<String, String>, Box<dyn std::error::Error + Send + Sync>> {
+        let filter = doc! {
+            "user_name": { "$in": user_names },
+            "profile.display_name": { "$type": "string" },
+        };
+        let options = FindOptions::builder()
+            .projection(doc! { "_id": 0, "user_name": 1, "profile.display_name": 1 })
+            .build();
+
+        let mut cursor = self
+            .collection
+            .clone_with_type::<Document>()
+            .find(filter, options)
+            .await?;
+
+        let mut display_names = HashMap::new();
+        while let Some(user) = cursor.try_next().await? {
+            if let (Ok(user_name), Ok(display_name)) = (
+                user.get_str("user_name"),
+                user.get_document("profile")
+                    .and_then(|profile| profile.get_str("display_name")),
+            ) {
+                display_names.insert(user_name.to_string(), display_name.to_string());
+            }
+        }
+
+        Ok(display_names)
+    }
+
+    async fn add_owned_polls(
+        &self,
+        user_id: String,
+        poll_ids: Vec<i64>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let filter = doc! { "user_id": &user_id };
+        let update = doc! { "$addToSet": { "owned_polls": { "$each": poll_ids } } };
+
+        let result = self.collection.update_one(filter, update, None).await?;
+
+        if result.matched_count == 0 {
+            eprintln!("User {} not found to update owned polls.", user_id);
+            return Err(Box::new(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                "User not found",
+            )));
+        }
 
         Ok(())
     }
@@ -159,6 +546,39 @@ impl UserRepository for MongoUserRepo {
         Ok(())
     }
 
+    async fn reserve_username(
+        &self,
+        user_name: String,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let filter = doc! { "user_name": &user_name };
+        let update = doc! { "$setOnInsert": { "reserved_at": bson::to_bson(&Utc::now())? } };
+        let options = UpdateOptions::builder().upsert(true).build();
+        self.reserved_usernames
+            .update_one(filter, update, options)
+            .await?;
+        Ok(())
+    }
+
+    async fn is_username_taken(
+        &self,
+        user_name: String,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        let filter = doc! { "user_name": &user_name };
+        if self
+            .collection
+            .count_documents(filter.clone(), None)
+            .await?
+            > 0
+        {
+            return Ok(true);
+        }
+        Ok(self
+            .reserved_usernames
+            .count_documents(filter, None)
+            .await?
+            > 0)
+    }
+
     async fn has_voted(
         &self,
         user_name: String,
@@ -166,7 +586,7 @@ impl UserRepository for MongoUserRepo {
     ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
         let filter = doc! {
             "user_name": user_name,
-            "votes": {
+            "polls_voted": {
                 "$elemMatch": {
                     "poll_id": poll_id
                 }
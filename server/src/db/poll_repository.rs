@@ -1,3 +1,5 @@
+use crate::models::poll_models::Ballot;
+use crate::models::poll_models::TallyIncrement;
 use crate::models::poll_models::VotingPoll;
 use crate::models::poll_models::VotingPollInput;
 use async_trait::async_trait;
@@ -7,6 +9,7 @@ pub trait PollRepository: Send + Sync {
     async fn create_poll(
         &self,
         poll: VotingPollInput,
+        creator: String,
     ) -> Result<VotingPoll, Box<dyn std::error::Error>>;
 
     async fn fetch_all(&self) -> Result<Vec<VotingPoll>, Box<dyn std::error::Error>>;
@@ -24,10 +27,44 @@ pub trait PollRepository: Send + Sync {
 
     async fn delete_poll(&self, poll_id: i64) -> Result<(), Box<dyn std::error::Error>>;
 
-    async fn vote_poll(
+    /// Fetches the polls with the given IDs; unknown IDs are skipped
+    async fn get_polls(
+        &self,
+        poll_ids: Vec<i64>,
+    ) -> Result<Vec<VotingPoll>, Box<dyn std::error::Error + Send + Sync>>;
+
+    /// Records `creator` as the creator of the given polls
+    async fn reassign_polls(
+        &self,
+        poll_ids: Vec<i64>,
+        creator: String,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>>;
+
+    async fn delete_polls(
+        &self,
+        poll_ids: Vec<i64>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>>;
+
+    /// Replaces a user with `tombstone` in every poll's `users_voted`, leaving
+    /// vote totals and ballots untouched
+    async fn anonymize_voter(
+        &self,
+        username: String,
+        tombstone: String,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>>;
+
+    /// The ballots cast in a poll
+    async fn get_ballots(
         &self,
         poll_id: i64,
-        option_id: i64,
+    ) -> Result<Vec<Ballot>, Box<dyn std::error::Error + Send + Sync>>;
+
+    /// Stores `ballot`, adds the `increments` to the options' totals and
+    /// marks `username` as having voted
+    async fn vote_poll(
+        &self,
+        ballot: Ballot,
+        increments: Vec<TallyIncrement>,
         username: String,
     ) -> Result<(), Box<dyn std::error::Error>>;
 }
@@ -0,0 +1,50 @@
+use async_trait::async_trait;
+use std::time::Duration;
+
+/// Token bucket shape: `capacity` requests at once, refilled by one every `refill_every`
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimitPolicy {
+    pub capacity: u32,
+    pub refill_every: Duration,
+}
+
+impl RateLimitPolicy {
+    pub const fn new(capacity: u32, refill_every: Duration) -> Self {
+        Self {
+            capacity,
+            refill_every,
+        }
+    }
+
+    /// Tokens added back per second
+    pub fn refill_rate(&self) -> f64 {
+        1.0 / self.refill_every.as_secs_f64()
+    }
+
+    /// How long an emptied bucket takes to fill up completely
+    pub fn full_refill(&self) -> Duration {
+        self.refill_every * self.capacity
+    }
+
+    /// How long until a bucket holding `tokens` has a whole token again
+    pub fn retry_after(&self, tokens: f64) -> Duration {
+        Duration::from_secs_f64(((1.0 - tokens) / self.refill_rate()).max(0.0))
+    }
+}
+
+/// Outcome of taking a token from a bucket
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RateDecision {
+    Allowed,
+    Limited { retry_after: Duration },
+}
+
+#[async_trait]
+pub trait RateLimitStore: Send + Sync {
+    /// Takes one token from the bucket stored under `key`, creating a full bucket if needed
+    async fn acquire(
+        &self,
+        key: &str,
+        policy: &RateLimitPolicy,
+    ) -> Result<RateDecision, Box<dyn std::error::Error + Send + Sync>>;
+}
@@ -0,0 +1,77 @@
+use crate::models::api_token_models::ApiToken;
+use crate::models::auth_jwt::Claims;
+use crate::models::token_models::RefreshToken;
+use async_trait::async_trait;
+
+#[async_trait]
+pub trait TokenRepository: Send + Sync {
+    async fn store_refresh_token(
+        &self,
+        token: RefreshToken,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>>;
+
+    async fn find_refresh_token(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<RefreshToken>, Box<dyn std::error::Error + Send + Sync>>;
+
+    /// Atomically marks a live refresh token as used, returning it if it could be consumed
+    async fn consume_refresh_token(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<RefreshToken>, Box<dyn std::error::Error + Send + Sync>>;
+
+    async fn revoke_refresh_family(
+        &self,
+        family_id: &str,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>>;
+
+    async fn revoke_user_refresh_tokens(
+        &self,
+        user_id: &str,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>>;
+
+    async fn revoke_access_token(
+        &self,
+        claims: &Claims,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>>;
+
+    /// Rejects every access token issued to the user up to now
+    async fn revoke_all_access_tokens(
+        &self,
+        user_id: &str,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>>;
+
+    async fn is_access_token_revoked(
+        &self,
+        claims: &Claims,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>>;
+
+    async fn store_api_token(
+        &self,
+        token: ApiToken,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>>;
+
+    /// Looks up an unexpired personal access token and records that it was used
+    async fn use_api_token(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<ApiToken>, Box<dyn std::error::Error + Send + Sync>>;
+
+    async fn list_api_tokens(
+        &self,
+        user_id: &str,
+    ) -> Result<Vec<ApiToken>, Box<dyn std::error::Error + Send + Sync>>;
+
+    /// Deletes one of the user's personal access tokens, returning whether it existed
+    async fn revoke_api_token(
+        &self,
+        user_id: &str,
+        token_id: &str,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>>;
+
+    async fn revoke_user_api_tokens(
+        &self,
+        user_id: &str,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>>;
+}
@@ -1,5 +1,9 @@
-use crate::models::user_models::{User, Votes};
+use crate::models::profile_models::{Avatar, Profile};
+use crate::models::user_models::{CredentialInfo, User};
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use webauthn_rs::prelude::Passkey;
 
 #[async_trait]
 pub trait UserRepository: Send + Sync {
@@ -10,14 +14,111 @@ pub trait UserRepository: Send + Sync {
         user_name: String,
     ) -> Result<Option<User>, Box<dyn std::error::Error + Send + Sync>>;
 
-    async fn update_user(
+    async fn get_user_by_id(
         &self,
-        user_name: String,
-        vote: Votes,
-    ) -> Result<(), Box<dyn std::error::Error>>;
+        user_id: String,
+    ) -> Result<Option<User>, Box<dyn std::error::Error + Send + Sync>>;
+
+    /// Finds the user owning the passkey with the given base64url credential ID
+    async fn get_user_by_credential(
+        &self,
+        credential_id: String,
+    ) -> Result<Option<User>, Box<dyn std::error::Error + Send + Sync>>;
+
+    /// Appends a passkey and its metadata to an existing user
+    async fn add_passkey(
+        &self,
+        user_id: String,
+        passkey: Passkey,
+        info: CredentialInfo,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>>;
+
+    /// Replaces the user's recovery codes with a new set of digests
+    async fn set_recovery_codes(
+        &self,
+        user_id: String,
+        code_hashes: Vec<String>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>>;
+
+    /// Atomically removes a recovery code from the user
+    ///
+    /// # Returns
+    /// The number of codes left if the code was valid, `None` otherwise
+    async fn consume_recovery_code(
+        &self,
+        user_id: String,
+        code_hash: String,
+    ) -> Result<Option<usize>, Box<dyn std::error::Error + Send + Sync>>;
+
+    /// Records a sign-in with one of the user's passkeys in place
+    ///
+    /// # Arguments
+    /// * `passkey` - The updated passkey (counter, backup state), if it changed
+    async fn update_credential(
+        &self,
+        user_id: String,
+        credential_id: String,
+        passkey: Option<Passkey>,
+        last_used_at: DateTime<Utc>,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>>;
+
+    /// Sets a passkey's nickname, returning false if the user has no such passkey
+    async fn rename_passkey(
+        &self,
+        user_id: String,
+        credential_id: String,
+        nickname: String,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>>;
+
+    /// Removes a passkey unless it is the user's last one, returning whether it was removed
+    async fn remove_passkey(
+        &self,
+        user_id: String,
+        credential_id: String,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>>;
+
+    /// Saves the user's profile, except for the avatar
+    async fn update_profile(
+        &self,
+        user_id: String,
+        profile: Profile,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>>;
+
+    /// Replaces or, given `None`, removes the user's avatar
+    async fn set_avatar(
+        &self,
+        user_id: String,
+        avatar: Option<Avatar>,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>>;
+
+    /// Maps each of the given usernames that has a display name onto it
+    async fn display_names(
+        &self,
+        user_names: Vec<String>,
+    ) -> Result<HashMap<String, String>, Box<dyn std::error::Error + Send + Sync>>;
+
+    /// Adds polls to the user's `owned_polls`
+    async fn add_owned_polls(
+        &self,
+        user_id: String,
+        poll_ids: Vec<i64>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>>;
 
     async fn delete_user(&self, user_id: String) -> Result<(), Box<dyn std::error::Error>>;
 
+    /// Keeps a deleted account's username from being registered again, so a
+    /// new account cannot vote again under the same name
+    async fn reserve_username(
+        &self,
+        user_name: String,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>>;
+
+    /// Whether `user_name` belongs to an account or was reserved when one was deleted
+    async fn is_username_taken(
+        &self,
+        user_name: String,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>>;
+
     async fn has_voted(
         &self,
         user_name: String,
@@ -1,14 +1,24 @@
+use actix_session::{config::PersistentSession, storage::CookieSessionStore, SessionMiddleware};
 use actix_web::{
+    cookie::Key,
     get,
+    http::Method,
     middleware::Logger,
     web::{self, Data, JsonConfig},
     App, HttpResponse, HttpServer, Responder,
 };
-use api::handler::auth_routes::{authentication, registration};
+use api::handler::account_routes::{delete_account, export, sign_in_history};
+use api::handler::admin_routes::auth_events;
+use api::handler::auth_routes::{
+    api_tokens, authentication, ceremony_stats, jwks, passkeys, recovery, registration, step_up,
+    tokens,
+};
+use api::handler::profile_routes::{delete_avatar, me, update_me, upload_avatar, user_avatar};
 use dotenv::dotenv;
-use log::info;
+use log::{info, warn};
 use std::env;
 use std::sync::Arc;
+use std::time::Duration;
 
 use actix_cors::Cors;
 use webauthn_rs::prelude::*;
@@ -16,6 +26,7 @@ use webauthn_rs::prelude::*;
 mod api;
 mod db;
 mod models;
+mod tasks;
 
 // Auth route handlers
 
@@ -24,13 +35,23 @@ use crate::api::handler::poll_routes::{
     add_polls, cast_vote, close_poll, delete_poll, fetch_polls, poll_results, reset_vote,
 };
 
+use crate::api::handler::middleware::rate_limit::{RateLimit, RouteGroup};
 use crate::db::{
-    db_config::DbConfig, init_poll_repo, init_user_repo, poll_repository::PollRepository,
+    audit_repository::AuditRepository,
+    db_config::DbConfig,
+    init_audit_repo, init_challenge_store, init_poll_repo, init_rate_limit_store, init_token_repo,
+    init_user_repo,
+    poll_repository::PollRepository,
+    rate_limit_store::{RateLimitPolicy, RateLimitStore},
+    token_repository::TokenRepository,
     user_repository::UserRepository,
 };
 use crate::models::{
-    authentication_state::AuthenticationState, registration_state::RegistrationState,
+    auth_event::TrustForwardedHeaders, authentication_state::AuthenticationState, jwt_keys,
+    registration_state::RegistrationState, rp_config::RelyingPartyConfig,
+    session_mode::SessionMode,
 };
+use crate::tasks::ceremony_reaper::{self, CeremonyConfig};
 
 /// Serve the root index.html file.
 #[get("/")]
@@ -45,26 +66,68 @@ async fn api_handler() -> impl Responder {
     HttpResponse::Ok().json("API is running.")
 }
 
-/// Configure WebAuthn.
-fn setup_webauthn() -> Data<Webauthn> {
-    // Read environment variables or use default values.
-    let rp_id = std::env::var("WEBAUTHN_ID").unwrap_or_else(|_| "localhost".to_string());
-    info!("{}", rp_id);
-    let rp_origin =
-        std::env::var("WEBAUTHN_ORIGIN").unwrap_or_else(|_| "http://localhost:3000".to_string());
-
-    info!("{}", rp_origin);
-    // Parse the origin URL.
-    let parsed_origin = Url::parse(&rp_origin).expect("Invalid URL for WebAuthn origin");
-
-    info!("{}", parsed_origin);
-    // Build the WebAuthn instance.
-    let webauthn = WebauthnBuilder::new(&rp_id, &parsed_origin)
-        .expect("Invalid WebAuthn configuration")
-        .build()
-        .expect("Failed to initialize WebAuthn");
-
-    Data::new(webauthn)
+/// Configure WebAuthn from the relying-party settings, refusing to start on invalid ones.
+fn setup_webauthn() -> (Data<Webauthn>, Data<RelyingPartyConfig>) {
+    let rp_config = RelyingPartyConfig::from_env()
+        .and_then(|config| config.build_webauthn().map(|webauthn| (webauthn, config)));
+
+    match rp_config {
+        Ok((webauthn, config)) => {
+            info!(
+                "WebAuthn relying party {} for origins {:?}",
+                config.rp_id,
+                config.origins.iter().map(Url::as_str).collect::<Vec<_>>()
+            );
+            (Data::new(webauthn), Data::new(config))
+        }
+        Err(err) => {
+            eprintln!("Invalid WebAuthn configuration: {}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Load the key used to sign session cookies.
+fn setup_session_key() -> Key {
+    // Every instance behind a load balancer must share the same key.
+    match env::var("SESSION_KEY") {
+        Ok(secret) if secret.len() >= 64 => Key::from(secret.as_bytes()),
+        Ok(_) => {
+            warn!("SESSION_KEY must be at least 64 bytes, generating a random key");
+            Key::generate()
+        }
+        Err(_) => {
+            warn!("SESSION_KEY is not set, generating a random key");
+            Key::generate()
+        }
+    }
+}
+
+/// Initialize the registration and authentication challenge stores.
+async fn setup_challenge_stores(
+    config: &DbConfig,
+    ceremony_config: CeremonyConfig,
+) -> (Data<RegistrationState>, Data<AuthenticationState>) {
+    let reg_state = init_challenge_store(config, "registration_ceremonies", ceremony_config.ttl)
+        .await
+        .unwrap_or_else(|err| {
+            eprintln!(
+                "Failed to initialize registration challenge store: {:?}",
+                err
+            );
+            std::process::exit(1);
+        });
+    let auth_state = init_challenge_store(config, "authentication_ceremonies", ceremony_config.ttl)
+        .await
+        .unwrap_or_else(|err| {
+            eprintln!(
+                "Failed to initialize authentication challenge store: {:?}",
+                err
+            );
+            std::process::exit(1);
+        });
+
+    (Data::from(reg_state), Data::from(auth_state))
 }
 
 /// Initialize database repositories.
@@ -94,6 +157,36 @@ async fn setup_repositories(
     }
 }
 
+/// Initialize the refresh token and revocation repository.
+async fn setup_token_repo(config: DbConfig) -> Data<dyn TokenRepository> {
+    match init_token_repo(config).await {
+        Ok(token_repo) => Data::from(Arc::new(token_repo) as Arc<dyn TokenRepository>),
+        Err(err) => {
+            eprintln!("Failed to initialize token repository: {:?}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Initialize the authentication audit log.
+async fn setup_audit_repo(config: DbConfig) -> Data<dyn AuditRepository> {
+    match init_audit_repo(config).await {
+        Ok(audit_repo) => Data::from(Arc::new(audit_repo) as Arc<dyn AuditRepository>),
+        Err(err) => {
+            eprintln!("Failed to initialize audit repository: {:?}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Initialize the rate limit bucket store.
+async fn setup_rate_limit_store(config: &DbConfig) -> Arc<dyn RateLimitStore> {
+    init_rate_limit_store(config).await.unwrap_or_else(|err| {
+        eprintln!("Failed to initialize rate limit store: {:?}", err);
+        std::process::exit(1);
+    })
+}
+
 /// Main application entry point.
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
@@ -104,19 +197,39 @@ async fn main() -> std::io::Result<()> {
         env::set_var("RUST_LOG", "info");
     }
 
+    // Refuse to start without usable token signing keys.
+    if let Err(err) = jwt_keys::init() {
+        eprintln!("Failed to load JWT keys: {}", err);
+        std::process::exit(1);
+    }
+
     // Database configuration.
-    let db_config = DbConfig::new(
+    let mut db_config = DbConfig::new(
         "mongodb", // This is already a `&str`, so no change needed
         env::var("DATABASE_URI")
             .unwrap_or_else(|_| "mongodb://localhost:27017/?directConnection=true".to_string())
             .as_str(), // Convert `String` to `&str` using `.as_str()`
         "polling_application", // This is already a `&str`, so no change needed
     );
+    if let Ok(challenge_store) = env::var("CHALLENGE_STORE") {
+        db_config.challenge_store = challenge_store;
+    }
+    if let Ok(rate_limit_store) = env::var("RATE_LIMIT_STORE") {
+        db_config.rate_limit_store = rate_limit_store;
+    }
+    let trust_forwarded = env::var("TRUST_FORWARDED_HEADERS").is_ok_and(|value| value == "true");
 
     // Set up shared state and repositories.
-    let webauthn = setup_webauthn();
-    let reg_state = Data::new(RegistrationState::new());
-    let auth_state = Data::new(AuthenticationState::new());
+    let (webauthn, rp_config) = setup_webauthn();
+    let ceremony_config = CeremonyConfig::from_env();
+    let (reg_state, auth_state) = setup_challenge_stores(&db_config, ceremony_config).await;
+    ceremony_reaper::spawn(ceremony_config, reg_state.clone(), auth_state.clone());
+    let session_key = setup_session_key();
+    let session_mode = SessionMode::from_env();
+    info!("Using {:?} session mode", session_mode);
+    let token_repo = setup_token_repo(db_config.clone()).await;
+    let audit_repo = setup_audit_repo(db_config.clone()).await;
+    let rate_limit_store = setup_rate_limit_store(&db_config).await;
     let (poll_repo, user_repo) = setup_repositories(db_config).await;
 
     let port = env::var("PORT").unwrap_or_else(|_| "3000".to_string());
@@ -125,29 +238,127 @@ async fn main() -> std::io::Result<()> {
     // Start the HTTP server.
     HttpServer::new(move || {
         App::new()
-            .wrap(Logger::default())
             .wrap(
+                RateLimit::new(rate_limit_store.clone())
+                    .trust_forwarded(trust_forwarded)
+                    .group(
+                        RouteGroup::new(
+                            "registration",
+                            &["/api/auth/start_reg/{username}", "/api/auth/finish_reg"],
+                        )
+                        .per_ip(RateLimitPolicy::new(10, Duration::from_secs(60)))
+                        .per_username(RateLimitPolicy::new(5, Duration::from_secs(60))),
+                    )
+                    .group(
+                        RouteGroup::new(
+                            "authentication",
+                            &[
+                                "/api/auth/start_auth/{username}",
+                                "/api/auth/finish_auth/{username}",
+                                "/api/auth/start_discoverable_auth",
+                                "/api/auth/finish_discoverable_auth",
+                            ],
+                        )
+                        .per_ip(RateLimitPolicy::new(30, Duration::from_secs(2)))
+                        .per_username(RateLimitPolicy::new(10, Duration::from_secs(30))),
+                    )
+                    .group(
+                        RouteGroup::new(
+                            "recovery",
+                            &["/api/auth/recovery/start", "/api/auth/recovery/finish"],
+                        )
+                        .per_ip(RateLimitPolicy::new(5, Duration::from_secs(300))),
+                    )
+                    .group(
+                        RouteGroup::new("votes", &["/api/polls/vote"])
+                            .method(Method::POST)
+                            .per_ip(RateLimitPolicy::new(60, Duration::from_secs(1)))
+                            .per_user(RateLimitPolicy::new(10, Duration::from_secs(6))),
+                    ),
+            )
+            // Outside the rate limiter, so per-user buckets can read cookie-mode sign-ins
+            .wrap({
+                let session =
+                    SessionMiddleware::builder(CookieSessionStore::default(), session_key.clone())
+                        .cookie_same_site(session_mode.same_site())
+                        .cookie_http_only(true)
+                        .cookie_secure(true);
+                match session_mode.session_ttl() {
+                    Some(ttl) => session
+                        .session_lifecycle(PersistentSession::default().session_ttl(ttl))
+                        .build(),
+                    None => session.build(),
+                }
+            })
+            .wrap(Logger::default())
+            .wrap({
+                // Credentialed requests are only accepted from the relying party's own origins
+                let rp_config = rp_config.clone();
                 Cors::default()
-                    .allow_any_origin()
+                    .allowed_origin_fn(move |origin, _| {
+                        origin
+                            .to_str()
+                            .is_ok_and(|origin| rp_config.allows_origin(origin))
+                    })
                     .allow_any_method()
                     .allow_any_header()
-                    .supports_credentials(),
-            )
+                    .supports_credentials()
+            })
             .app_data(webauthn.clone())
+            .app_data(rp_config.clone())
+            .app_data(Data::new(session_mode))
+            .app_data(Data::new(TrustForwardedHeaders(trust_forwarded)))
             .app_data(reg_state.clone())
             .app_data(auth_state.clone())
             .app_data(poll_repo.clone())
             .app_data(user_repo.clone())
+            .app_data(token_repo.clone())
+            .app_data(audit_repo.clone())
             .app_data(JsonConfig::default())
             .service(root_handler)
             .service(api_handler)
+            .service(jwks)
             .service(
                 web::scope("/api/auth")
                     .service(registration::start)
                     .service(registration::finish)
                     .service(authentication::start)
-                    .service(authentication::finish),
+                    .service(authentication::finish)
+                    .service(authentication::start_discoverable)
+                    .service(authentication::finish_discoverable)
+                    .service(ceremony_stats)
+                    .service(tokens::refresh)
+                    .service(tokens::logout)
+                    .service(tokens::logout_all)
+                    .service(passkeys::start)
+                    .service(passkeys::finish)
+                    .service(passkeys::list)
+                    .service(passkeys::rename)
+                    .service(passkeys::revoke)
+                    .service(step_up::start)
+                    .service(step_up::finish)
+                    .service(recovery::regenerate)
+                    .service(recovery::start)
+                    .service(recovery::finish)
+                    .service(api_tokens::create)
+                    .service(api_tokens::list)
+                    .service(api_tokens::revoke),
+            )
+            .service(
+                web::scope("/api/account")
+                    .service(export)
+                    .service(sign_in_history)
+                    .service(delete_account),
+            )
+            .service(
+                web::scope("/api/me")
+                    .service(me)
+                    .service(update_me)
+                    .service(upload_avatar)
+                    .service(delete_avatar),
             )
+            .service(web::scope("/api/users").service(user_avatar))
+            .service(web::scope("/api/admin").service(auth_events))
             .service(
                 web::scope("/api")
                     .service(add_polls)
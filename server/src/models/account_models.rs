@@ -0,0 +1,50 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::models::poll_models::VotingPoll;
+use crate::models::profile_models::ProfileView;
+use crate::models::user_models::{CredentialInfo, Role, Votes};
+
+/// Creator recorded on anonymized polls; not a valid username, so nobody can claim them
+pub const DELETED_CREATOR: &str = "[deleted]";
+
+/// Stands in for a deleted account in the voter lists of polls it voted in;
+/// like `DELETED_CREATOR` it is not a valid username
+pub fn deleted_voter(user_id: &str) -> String {
+    format!("[deleted:{}]", user_id)
+}
+
+/// What happens to the polls of a deleted account
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PollPolicy {
+    /// Hand the polls over to another user
+    Transfer,
+    /// Keep the polls but drop the creator's name
+    Anonymize,
+    /// Delete the polls
+    Delete,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeleteAccountQuery {
+    pub polls: PollPolicy,
+    /// Username receiving the polls under `PollPolicy::Transfer`
+    pub transfer_to: Option<String>,
+}
+
+/// Everything stored about a user, as handed out by the export endpoint
+#[derive(Debug, Serialize)]
+pub struct AccountExport {
+    pub exported_at: DateTime<Utc>,
+    pub user_id: String,
+    pub user_name: String,
+    pub roles: Vec<Role>,
+    pub profile: ProfileView,
+    pub credentials: Vec<CredentialInfo>,
+    pub recovery_codes_remaining: usize,
+    pub polls_voted: Vec<Votes>,
+    pub owned_polls: Vec<i64>,
+    /// Full contents of the polls in `owned_polls`
+    pub polls: Vec<VotingPoll>,
+}
@@ -0,0 +1,93 @@
+use chrono::{DateTime as ChronoDateTime, Utc};
+use mongodb::bson::DateTime;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Prefix telling personal access tokens apart from JWTs
+pub const API_TOKEN_PREFIX: &str = "pat_";
+/// Lifetime of a personal access token when none is requested
+pub const DEFAULT_API_TOKEN_TTL_DAYS: u32 = 90;
+/// Longest lifetime a personal access token can be given
+pub const MAX_API_TOKEN_TTL_DAYS: u32 = 365;
+
+/// Operations a personal access token can be allowed to perform
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    /// Read polls and results; anonymous reads stay allowed, but a token sent to them needs this scope
+    #[serde(rename = "polls:read")]
+    PollsRead,
+    /// Create, reset and close polls; deleting one needs a recent sign-in
+    #[serde(rename = "polls:write")]
+    PollsWrite,
+    /// Cast votes
+    #[serde(rename = "votes:write")]
+    VotesWrite,
+}
+
+/// A personal access token as stored server-side; only the SHA-256 digest of the token is kept
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ApiToken {
+    pub token_id: String,
+    pub token_hash: String,
+    pub user_id: String,
+    pub name: String,
+    pub scopes: Vec<Scope>,
+    pub created_at: DateTime,
+    pub expires_at: DateTime,
+    pub last_used_at: Option<DateTime>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateApiTokenRequest {
+    pub name: String,
+    pub scopes: Vec<Scope>,
+    /// Days until the token expires, `DEFAULT_API_TOKEN_TTL_DAYS` if omitted
+    pub expires_in_days: Option<u32>,
+}
+
+/// What the owner gets to see of a token; never includes the secret
+#[derive(Debug, Serialize)]
+pub struct ApiTokenInfo {
+    pub token_id: String,
+    pub name: String,
+    pub scopes: Vec<Scope>,
+    pub created_at: ChronoDateTime<Utc>,
+    pub expires_at: ChronoDateTime<Utc>,
+    pub last_used_at: Option<ChronoDateTime<Utc>>,
+}
+
+impl From<&ApiToken> for ApiTokenInfo {
+    fn from(token: &ApiToken) -> Self {
+        Self {
+            token_id: token.token_id.clone(),
+            name: token.name.clone(),
+            scopes: token.scopes.clone(),
+            created_at: token.created_at.to_chrono(),
+            expires_at: token.expires_at.to_chrono(),
+            last_used_at: token.last_used_at.map(|at| at.to_chrono()),
+        }
+    }
+}
+
+/// A newly minted token; the secret is shown this once
+#[derive(Debug, Serialize)]
+pub struct CreatedApiToken {
+    pub token: String,
+    #[serde(flatten)]
+    pub info: ApiTokenInfo,
+}
+
+/// Generates a new opaque personal access token
+pub fn generate_api_token() -> String {
+    format!(
+        "{}{}{}",
+        API_TOKEN_PREFIX,
+        Uuid::new_v4().simple(),
+        Uuid::new_v4().simple()
+    )
+}
+
+/// Digest under which a personal access token is stored and looked up
+pub fn hash_api_token(token: &str) -> String {
+    sha256::digest(token)
+}
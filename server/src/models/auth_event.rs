@@ -0,0 +1,153 @@
+use actix_web::{http::header::USER_AGENT, web::Data, HttpRequest};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Longest user agent string kept on an event
+const MAX_USER_AGENT_LEN: usize = 256;
+/// Entries returned when a query does not ask for a number
+pub const DEFAULT_EVENT_LIMIT: i64 = 20;
+/// Most entries a single query can return
+pub const MAX_EVENT_LIMIT: i64 = 500;
+
+/// Whether `X-Forwarded-For` and `Forwarded` name the client, which only holds
+/// behind a proxy that sets them (`TRUST_FORWARDED_HEADERS=true`)
+#[derive(Debug, Clone, Copy)]
+pub struct TrustForwardedHeaders(pub bool);
+
+/// What an audit event records
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthEventType {
+    Registration,
+    Login,
+    /// A sign-in was refused because the authenticator's counter went backwards
+    CounterRegression,
+    /// A signed-in user re-verified themselves before a destructive operation
+    StepUp,
+    /// A recovery code was tried, or the passkey enrolled with one completed the recovery
+    Recovery,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthOutcome {
+    Success,
+    Failure,
+}
+
+/// One entry of the append-only authentication audit log
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AuthEvent {
+    pub event_type: AuthEventType,
+    pub outcome: AuthOutcome,
+    pub user_id: Option<String>,
+    pub username: Option<String>,
+    pub credential_id: Option<String>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    /// Why the attempt failed, for failures
+    pub detail: Option<String>,
+    pub occurred_at: bson::DateTime,
+}
+
+impl AuthEvent {
+    /// Starts an event for the given request; the outcome is filled in by `finish`
+    pub fn new(event_type: AuthEventType, req: &HttpRequest) -> Self {
+        let user_agent = req
+            .headers()
+            .get(USER_AGENT)
+            .and_then(|agent| agent.to_str().ok())
+            .map(|agent| agent.chars().take(MAX_USER_AGENT_LEN).collect());
+
+        // Clients can send forwarding headers themselves, so only a proxy's are believed
+        let trust_forwarded = req
+            .app_data::<Data<TrustForwardedHeaders>>()
+            .is_some_and(|trust| trust.0);
+        let connection_info = req.connection_info();
+        let ip = if trust_forwarded {
+            connection_info.realip_remote_addr()
+        } else {
+            connection_info.peer_addr()
+        }
+        .map(str::to_string);
+
+        Self {
+            event_type,
+            outcome: AuthOutcome::Failure,
+            user_id: None,
+            username: None,
+            credential_id: None,
+            ip,
+            user_agent,
+            detail: None,
+            occurred_at: bson::DateTime::now(),
+        }
+    }
+
+    /// Records the outcome of the request the event describes
+    pub fn finish<T, E: ToString>(mut self, result: &Result<T, E>) -> Self {
+        match result {
+            Ok(_) => self.outcome = AuthOutcome::Success,
+            Err(e) => {
+                self.outcome = AuthOutcome::Failure;
+                self.detail = Some(e.to_string());
+            }
+        }
+        self
+    }
+}
+
+/// An audit event as returned by the API
+#[derive(Debug, Serialize)]
+pub struct AuthEventEntry {
+    pub event_type: AuthEventType,
+    pub outcome: AuthOutcome,
+    pub user_id: Option<String>,
+    pub username: Option<String>,
+    pub credential_id: Option<String>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub detail: Option<String>,
+    pub occurred_at: DateTime<Utc>,
+}
+
+impl From<AuthEvent> for AuthEventEntry {
+    fn from(event: AuthEvent) -> Self {
+        Self {
+            event_type: event.event_type,
+            outcome: event.outcome,
+            user_id: event.user_id,
+            username: event.username,
+            credential_id: event.credential_id,
+            ip: event.ip,
+            user_agent: event.user_agent,
+            detail: event.detail,
+            occurred_at: event.occurred_at.to_chrono(),
+        }
+    }
+}
+
+/// Filters for the admin audit log query; every field is optional
+#[derive(Debug, Deserialize)]
+pub struct AuthEventQuery {
+    pub user_id: Option<String>,
+    pub username: Option<String>,
+    pub event_type: Option<AuthEventType>,
+    pub outcome: Option<AuthOutcome>,
+    pub ip: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HistoryQuery {
+    pub limit: Option<i64>,
+}
+
+/// The number of entries to return for a requested `limit`
+pub fn event_limit(limit: Option<i64>) -> i64 {
+    limit
+        .unwrap_or(DEFAULT_EVENT_LIMIT)
+        .clamp(1, MAX_EVENT_LIMIT)
+}
@@ -1,16 +1,79 @@
-use actix_web::{Error, FromRequest, HttpMessage, HttpRequest}; // Add HttpMessage import
-use chrono::{Duration, Utc};
+use actix_web::{
+    error::InternalError, http::header::WWW_AUTHENTICATE, Error, FromRequest, HttpMessage,
+    HttpRequest, HttpResponse,
+}; // Add HttpMessage import
+use chrono::{DateTime, Duration, Utc};
 use dotenv::dotenv;
-use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, TokenData, Validation};
+use jsonwebtoken::{
+    decode, decode_header, encode, errors::ErrorKind, Header, TokenData, Validation,
+};
 use serde::{Deserialize, Serialize};
 use std::env;
 use uuid::Uuid;
 
+use crate::models::{jwt_keys::keys, user_models::Role};
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Claims {
-    pub exp: usize, // Expiration time
-    pub iat: usize, // Issued at time
-    pub uuid: Uuid, // User unique identifier
+    pub exp: usize,  // Expiration time
+    pub iat: usize,  // Issued at time
+    pub uuid: Uuid,  // User unique identifier
+    pub jti: String, // Token identifier, used for revocation
+    #[serde(default)]
+    pub roles: Vec<Role>, // Roles held by the user when the token was issued
+    /// When the user performed the WebAuthn assertion this token was minted by;
+    /// refreshed tokens do not carry it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth_time: Option<usize>,
+    /// How the user authenticated, as RFC 8176 method references
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub amr: Vec<String>,
+}
+
+impl Claims {
+    /// Whether the token carries the given role
+    pub fn has_role(&self, role: Role) -> bool {
+        self.roles.contains(&role)
+    }
+
+    /// Whether the token was minted by a user-verified assertion no older than `max_age`
+    pub fn verified_within(&self, max_age: Duration) -> bool {
+        let Some(auth_time) = self.auth_time else {
+            return false;
+        };
+        self.amr.iter().any(|method| method == AMR_MFA)
+            && auth_time as i64 >= (Utc::now() - max_age).timestamp()
+    }
+}
+
+/// Method reference for proof of possession of a hardware-bound key
+const AMR_HWK: &str = "hwk";
+/// Method reference for an assertion that also verified the user (PIN or biometric)
+const AMR_MFA: &str = "mfa";
+
+/// The WebAuthn assertion a token is minted from
+#[derive(Clone, Copy, Debug)]
+pub struct AuthContext {
+    pub auth_time: DateTime<Utc>,
+    pub user_verified: bool,
+}
+
+impl AuthContext {
+    /// An assertion that just completed
+    pub fn now(user_verified: bool) -> Self {
+        Self {
+            auth_time: Utc::now(),
+            user_verified,
+        }
+    }
+
+    fn amr(&self) -> Vec<String> {
+        let mut amr = vec![AMR_HWK.to_string()];
+        if self.user_verified {
+            amr.push(AMR_MFA.to_string());
+        }
+        amr
+    }
 }
 
 impl FromRequest for Claims {
@@ -28,34 +91,138 @@ impl FromRequest for Claims {
     }
 }
 
-/// Encodes a JWT with the given user UUID, setting an expiration time of 24 hours.
-pub fn encode_jwt(uuid: &Uuid) -> Result<String, jsonwebtoken::errors::Error> {
+/// Claims of a caller who verified themselves on their authenticator within
+/// `step_up_max_age`; handlers for destructive operations take this instead of `Claims`
+#[derive(Clone, Debug)]
+pub struct RecentAuth(pub Claims);
+
+impl FromRequest for RecentAuth {
+    type Error = Error;
+    type Future = std::future::Ready<Result<Self, Self::Error>>;
+
+    fn from_request(
+        req: &HttpRequest,
+        _payload: &mut actix_web::dev::Payload,
+    ) -> std::future::Ready<Result<RecentAuth, Self::Error>> {
+        let Some(claims) = req.extensions().get::<Claims>().cloned() else {
+            return std::future::ready(Err(actix_web::error::ErrorBadRequest("Bad Claims")));
+        };
+        let max_age = step_up_max_age();
+        if claims.verified_within(max_age) {
+            return std::future::ready(Ok(RecentAuth(claims)));
+        }
+
+        // RFC 9470 challenge telling the client to run the step-up ceremony
+        let response = HttpResponse::Unauthorized()
+            .insert_header((
+                WWW_AUTHENTICATE,
+                format!(
+                    "Bearer error=\"insufficient_user_authentication\", \
+                     error_description=\"Recent user verification required\", max_age={}",
+                    max_age.num_seconds()
+                ),
+            ))
+            .json("Recent user verification required");
+        std::future::ready(Err(InternalError::from_response(
+            "Recent user verification required",
+            response,
+        )
+        .into()))
+    }
+}
+
+/// Lifetime of an access token, `ACCESS_TOKEN_TTL_MINS` minutes (15 by default).
+pub fn access_token_ttl() -> Duration {
+    let minutes = env::var("ACCESS_TOKEN_TTL_MINS")
+        .ok()
+        .and_then(|minutes| minutes.parse().ok())
+        .unwrap_or(15);
+    Duration::minutes(minutes)
+}
+
+/// Lifetime of a refresh token, `REFRESH_TOKEN_TTL_DAYS` days (30 by default).
+pub fn refresh_token_ttl() -> Duration {
+    let days = env::var("REFRESH_TOKEN_TTL_DAYS")
+        .ok()
+        .and_then(|days| days.parse().ok())
+        .unwrap_or(30);
+    Duration::days(days)
+}
+
+/// How recently a destructive operation needs the user to have been verified,
+/// `STEP_UP_MAX_AGE_MINS` minutes (5 by default); also the lifetime of a step-up token.
+pub fn step_up_max_age() -> Duration {
+    let minutes = env::var("STEP_UP_MAX_AGE_MINS")
+        .ok()
+        .and_then(|minutes| minutes.parse().ok())
+        .unwrap_or(5);
+    Duration::minutes(minutes)
+}
+
+/// Encodes a short-lived JWT with the given user UUID and roles.
+///
+/// Tokens minted by a WebAuthn assertion record it through `auth`.
+pub fn encode_jwt(
+    uuid: &Uuid,
+    roles: &[Role],
+    auth: Option<AuthContext>,
+) -> Result<String, jsonwebtoken::errors::Error> {
     dotenv().ok(); // Load environment variables from `.env` file
+    encode_claims(uuid, roles, auth, access_token_ttl())
+}
+
+/// Encodes an elevated JWT proving a step-up assertion, valid for `step_up_max_age`.
+pub fn encode_step_up_jwt(
+    uuid: &Uuid,
+    roles: &[Role],
+    auth: AuthContext,
+) -> Result<String, jsonwebtoken::errors::Error> {
+    encode_claims(uuid, roles, Some(auth), step_up_max_age())
+}
+
+fn encode_claims(
+    uuid: &Uuid,
+    roles: &[Role],
+    auth: Option<AuthContext>,
+    expire: Duration,
+) -> Result<String, jsonwebtoken::errors::Error> {
     let now = Utc::now();
-    let expire = Duration::hours(24); // JWT expiration time
 
     let claims = Claims {
         exp: (now + expire).timestamp() as usize,
         iat: now.timestamp() as usize,
         uuid: *uuid,
+        roles: roles.to_vec(),
+        jti: Uuid::new_v4().to_string(),
+        auth_time: auth.map(|auth| auth.auth_time.timestamp() as usize),
+        amr: auth.map(|auth| auth.amr()).unwrap_or_default(),
     };
 
-    let secret = env::var("SECRET").unwrap_or_else(|_| "notsosecuresecret".to_string()); // Use a default secret if not set
+    let keys = keys();
+    let mut header = Header::new(keys.algorithm);
+    header.kid = Some(keys.kid.clone());
 
-    encode(
-        &Header::default(),
-        &claims,
-        &EncodingKey::from_secret(secret.as_ref()),
-    )
+    encode(&header, &claims, &keys.signing_key)
 }
 
 /// Decodes a JWT and returns the claims embedded within the token.
+///
+/// The token's `kid` selects the verification key, so tokens signed by a retired key
+/// stay valid for as long as its public key is still published.
 pub fn decode_jwt(jwt: String) -> Result<TokenData<Claims>, jsonwebtoken::errors::Error> {
-    let secret = env::var("SECRET").unwrap_or_else(|_| "notsosecuresecret".to_string()); // Secret used for decoding
+    let keys = keys();
+    let header = decode_header(&jwt)?;
+    let kid = header.kid.unwrap_or_else(|| keys.kid.clone());
+
+    let verification_key = keys
+        .verification_keys
+        .get(&kid)
+        .ok_or_else(|| jsonwebtoken::errors::Error::from(ErrorKind::InvalidToken))?;
 
+    // The algorithm is pinned by the key, never taken from the token header
     decode(
         &jwt,
-        &DecodingKey::from_secret(secret.as_ref()),
-        &Validation::default(),
+        &verification_key.key,
+        &Validation::new(verification_key.algorithm),
     )
 }
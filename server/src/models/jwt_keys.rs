@@ -0,0 +1,169 @@
+use jsonwebtoken::{
+    jwk::{AlgorithmParameters, EllipticCurve, Jwk, JwkSet},
+    Algorithm, DecodingKey, EncodingKey,
+};
+use log::warn;
+use once_cell::sync::OnceCell;
+use std::collections::HashMap;
+use std::{env, fs};
+use thiserror::Error;
+
+/// Secret that `SECRET` used to silently fall back to; only accepted in dev mode
+const INSECURE_DEFAULT_SECRET: &str = "notsosecuresecret";
+
+static KEYS: OnceCell<JwtKeys> = OnceCell::new();
+
+#[derive(Debug, Error)]
+pub enum JwtKeyError {
+    #[error("Unsupported JWT_ALGORITHM: {0}")]
+    UnsupportedAlgorithm(String),
+    #[error("{0} must be set")]
+    Missing(&'static str),
+    #[error("SECRET is unset or insecure; set a strong SECRET or DEV_MODE=true")]
+    InsecureSecret,
+    #[error("Failed to read {0}: {1}")]
+    Io(String, std::io::Error),
+    #[error("Invalid key {0}: {1}")]
+    InvalidKey(String, String),
+    #[error("The JWKS does not contain the active key {0}")]
+    ActiveKeyNotPublished(String),
+    #[error("JWT keys are already initialized")]
+    AlreadyInitialized,
+}
+
+/// Key used to verify tokens carrying a given `kid`
+pub struct VerificationKey {
+    pub algorithm: Algorithm,
+    pub key: DecodingKey,
+}
+
+/// Signing key and verification keys for access tokens
+///
+/// Configured through the environment:
+/// * `JWT_ALGORITHM` - `HS256` (default), `EdDSA` or `ES256`
+/// * `JWT_KID` - key ID of the active signing key
+/// * `JWT_PRIVATE_KEY_FILE` - PKCS#8 PEM of the active signing key (asymmetric only)
+/// * `JWT_JWKS_FILE` - JWK set of every public key still accepted (asymmetric only);
+///   keep retired keys here until the tokens they signed have expired
+/// * `SECRET` - shared secret (HS256 only)
+pub struct JwtKeys {
+    pub algorithm: Algorithm,
+    pub kid: String,
+    pub signing_key: EncodingKey,
+    pub verification_keys: HashMap<String, VerificationKey>,
+    /// Public keys published at `/.well-known/jwks.json`; empty for HS256
+    pub jwks: JwkSet,
+}
+
+impl JwtKeys {
+    pub fn from_env() -> Result<Self, JwtKeyError> {
+        let dev_mode = env::var("DEV_MODE").is_ok_and(|value| value == "true");
+        let algorithm = env::var("JWT_ALGORITHM").unwrap_or_else(|_| "HS256".to_string());
+
+        match algorithm.as_str() {
+            "HS256" => Self::symmetric(dev_mode),
+            "EdDSA" => Self::asymmetric(Algorithm::EdDSA),
+            "ES256" => Self::asymmetric(Algorithm::ES256),
+            _ => Err(JwtKeyError::UnsupportedAlgorithm(algorithm)),
+        }
+    }
+
+    fn symmetric(dev_mode: bool) -> Result<Self, JwtKeyError> {
+        let secret = match env::var("SECRET") {
+            Ok(secret) if !secret.is_empty() && secret != INSECURE_DEFAULT_SECRET => secret,
+            _ if dev_mode => {
+                warn!("DEV_MODE is enabled, signing tokens with the insecure default secret");
+                INSECURE_DEFAULT_SECRET.to_string()
+            }
+            _ => return Err(JwtKeyError::InsecureSecret),
+        };
+        let kid = env::var("JWT_KID").unwrap_or_else(|_| "hs256".to_string());
+
+        let mut verification_keys = HashMap::new();
+        verification_keys.insert(
+            kid.clone(),
+            VerificationKey {
+                algorithm: Algorithm::HS256,
+                key: DecodingKey::from_secret(secret.as_ref()),
+            },
+        );
+
+        Ok(JwtKeys {
+            algorithm: Algorithm::HS256,
+            kid,
+            signing_key: EncodingKey::from_secret(secret.as_ref()),
+            verification_keys,
+            jwks: JwkSet { keys: Vec::new() },
+        })
+    }
+
+    fn asymmetric(algorithm: Algorithm) -> Result<Self, JwtKeyError> {
+        let kid = env::var("JWT_KID").map_err(|_| JwtKeyError::Missing("JWT_KID"))?;
+        let private_key_file = env::var("JWT_PRIVATE_KEY_FILE")
+            .map_err(|_| JwtKeyError::Missing("JWT_PRIVATE_KEY_FILE"))?;
+        let jwks_file =
+            env::var("JWT_JWKS_FILE").map_err(|_| JwtKeyError::Missing("JWT_JWKS_FILE"))?;
+
+        let private_key =
+            fs::read(&private_key_file).map_err(|e| JwtKeyError::Io(private_key_file, e))?;
+        let signing_key = match algorithm {
+            Algorithm::EdDSA => EncodingKey::from_ed_pem(&private_key),
+            _ => EncodingKey::from_ec_pem(&private_key),
+        }
+        .map_err(|e| JwtKeyError::InvalidKey(kid.clone(), e.to_string()))?;
+
+        let jwks = fs::read_to_string(&jwks_file).map_err(|e| JwtKeyError::Io(jwks_file, e))?;
+        let jwks: JwkSet = serde_json::from_str(&jwks)
+            .map_err(|e| JwtKeyError::InvalidKey("JWKS".to_string(), e.to_string()))?;
+
+        let mut verification_keys = HashMap::new();
+        for jwk in &jwks.keys {
+            let key_id = jwk.common.key_id.clone().ok_or_else(|| {
+                JwtKeyError::InvalidKey("JWKS".to_string(), "every key needs a kid".to_string())
+            })?;
+            let verification_key = Self::verification_key(jwk)
+                .map_err(|e| JwtKeyError::InvalidKey(key_id.clone(), e))?;
+            verification_keys.insert(key_id, verification_key);
+        }
+
+        match verification_keys.get(&kid) {
+            Some(active) if active.algorithm == algorithm => {}
+            _ => return Err(JwtKeyError::ActiveKeyNotPublished(kid)),
+        }
+
+        Ok(JwtKeys {
+            algorithm,
+            kid,
+            signing_key,
+            verification_keys,
+            jwks,
+        })
+    }
+
+    /// Builds the verification key for a published JWK, pinning the algorithm to its curve
+    fn verification_key(jwk: &Jwk) -> Result<VerificationKey, String> {
+        let algorithm = match &jwk.algorithm {
+            AlgorithmParameters::OctetKeyPair(params) if params.curve == EllipticCurve::Ed25519 => {
+                Algorithm::EdDSA
+            }
+            AlgorithmParameters::EllipticCurve(params) if params.curve == EllipticCurve::P256 => {
+                Algorithm::ES256
+            }
+            _ => return Err("only Ed25519 and P-256 keys are supported".to_string()),
+        };
+        let key = DecodingKey::from_jwk(jwk).map_err(|e| e.to_string())?;
+
+        Ok(VerificationKey { algorithm, key })
+    }
+}
+
+/// Loads the JWT keys from the environment; must be called once at startup
+pub fn init() -> Result<(), JwtKeyError> {
+    let keys = JwtKeys::from_env()?;
+    KEYS.set(keys).map_err(|_| JwtKeyError::AlreadyInitialized)
+}
+
+/// The keys loaded by `init`
+pub fn keys() -> &'static JwtKeys {
+    KEYS.get().expect("JWT keys are not initialized")
+}
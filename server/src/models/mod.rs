@@ -1,5 +1,16 @@
+pub mod account_models;
+pub mod api_token_models;
+pub mod auth_event;
 pub mod auth_jwt;
 pub mod authentication_state;
+pub mod jwt_keys;
 pub mod poll_models;
+pub mod profile_models;
+pub mod recovery_codes;
 pub mod registration_state;
+pub mod rp_config;
+pub mod security_event;
+pub mod session_mode;
+pub mod tabulation;
+pub mod token_models;
 pub mod user_models;
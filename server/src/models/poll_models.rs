@@ -1,16 +1,392 @@
 use chrono::{DateTime, Utc};
 use futures::Stream;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::pin::Pin;
 use std::task::{Context, Poll as TaskPoll}; // Renamed to avoid conflict
+use thiserror::Error;
 use tokio::sync::mpsc;
 
+use crate::models::tabulation::Tabulation;
+use crate::models::user_models::normalize_username;
+
 /// Represents an option within a poll
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct PollOption {
     pub option_id: i64, // Unique ID for the option
     pub text: String,   // Text description of the option
-    pub votes: i32,     // Number of votes this option has received
+    pub votes: i32, // Number of votes this option has received, weighted; total points on score polls
+    /// Number of ballots counting toward the option, regardless of weight; `None` on
+    /// polls that predate voter counts and have not been reset since
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub voters: Option<i32>,
+    /// Weighted count of ballots giving the option each score, indexed by score; empty unless the poll is scored
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub score_counts: Vec<i32>,
+}
+
+/// Highest `max_score` a score poll may use
+pub const SCORE_LIMIT: u32 = 10;
+
+fn default_max_score() -> u32 {
+    5
+}
+
+/// Largest credit budget of a quadratic poll
+pub const CREDIT_LIMIT: u32 = 10_000;
+
+fn default_credits() -> u32 {
+    100
+}
+
+/// Largest weight a voter can be given
+pub const WEIGHT_LIMIT: u32 = 100;
+
+fn default_weight() -> u32 {
+    1
+}
+
+/// How voters fill in their ballot
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum VotingMethod {
+    /// Exactly one option per voter
+    #[default]
+    SingleChoice,
+    /// Any number of options, each approved option getting one vote
+    Approval,
+    /// Between one and `max_choices` options
+    MultiSelect { max_choices: usize },
+    /// Options ranked in order of preference, counted by instant runoff;
+    /// voters need not rank every option
+    RankedChoice,
+    /// Ranked ballots counted pairwise, the winner found by the Schulze method
+    Schulze,
+    /// Ranked ballots counted pairwise, the winner found by ranked pairs
+    RankedPairs,
+    /// Every option scored from 0 to `max_score`; the highest total wins
+    Score {
+        #[serde(default = "default_max_score")]
+        max_score: u32,
+    },
+    /// Scored like `Score`, then the two highest-scoring options go to an
+    /// automatic runoff won by the one more ballots score higher
+    Star {
+        #[serde(default = "default_max_score")]
+        max_score: u32,
+    },
+    /// Each voter spreads votes across options, `n` votes on one option
+    /// costing `n²` of their `credits`
+    Quadratic {
+        #[serde(default = "default_credits")]
+        credits: u32,
+    },
+}
+
+/// Why a poll cannot be created as submitted
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum PollInputError {
+    #[error("A poll needs at least one option")]
+    NoOptions,
+    #[error("max_choices must be between 1 and the number of options ({0})")]
+    MaxChoices(usize),
+    #[error("max_score must be between 1 and {SCORE_LIMIT}")]
+    MaxScore,
+    #[error("credits must be between 1 and {CREDIT_LIMIT}")]
+    Credits,
+    #[error("Invalid username {0:?} in eligible_voters")]
+    EligibleVoter(String),
+    #[error("User {0} is listed more than once in eligible_voters")]
+    DuplicateEligibleVoter(String),
+    #[error("Voter weights must be between 1 and {WEIGHT_LIMIT}")]
+    Weight,
+}
+
+/// Why a ballot does not fit the poll's voting method
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum BallotError {
+    #[error("No option selected")]
+    Empty,
+    #[error("Option {0} is selected more than once")]
+    Duplicate(i64),
+    #[error("Option {0} does not exist in this poll")]
+    UnknownOption(i64),
+    #[error("This poll accepts exactly one option")]
+    SingleChoice,
+    #[error("This poll accepts at most {0} options")]
+    TooManyChoices(usize),
+    #[error("This poll takes a selection of options, not scores")]
+    ScoresNotAccepted,
+    #[error("This poll takes a score for every option")]
+    ScoresRequired,
+    #[error("Scores must be between 0 and {0}")]
+    ScoreOutOfRange(u32),
+    #[error("Option {0} has no score")]
+    Unscored(i64),
+    #[error("This poll does not take vote allocations")]
+    AllocationsNotAccepted,
+    #[error("This poll takes a number of votes per option")]
+    AllocationsRequired,
+    #[error("These votes cost {cost} credits, more than the {credits} available")]
+    OverBudget { cost: u64, credits: u32 },
+}
+
+/// The score a voter gave one option
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub struct OptionScore {
+    pub option_id: i64,
+    pub score: u32,
+}
+
+/// The votes a voter put on one option of a quadratic poll
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub struct Allocation {
+    pub option_id: i64,
+    pub votes: u32,
+}
+
+/// What one ballot adds to an option's stored totals
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TallyIncrement {
+    pub option_id: i64,
+    /// Votes added to the option's total, already multiplied by the voter's weight
+    pub votes: i32,
+    /// The score to count in the option's histogram, on score polls
+    pub score: Option<u32>,
+    /// The voter's weight, added to the histogram entry of `score`
+    pub weight: i32,
+}
+
+/// A user allowed to vote in a poll that restricts voting
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct EligibleVoter {
+    pub user_name: String,
+    /// How many times the voter's ballot counts
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+}
+
+impl VotingMethod {
+    /// Whether voters rank the options they select
+    pub fn is_ranked(&self) -> bool {
+        matches!(
+            self,
+            VotingMethod::RankedChoice | VotingMethod::Schulze | VotingMethod::RankedPairs
+        )
+    }
+
+    /// The highest score a voter can give, if voters score the options
+    pub fn max_score(&self) -> Option<u32> {
+        match *self {
+            VotingMethod::Score { max_score } | VotingMethod::Star { max_score } => Some(max_score),
+            _ => None,
+        }
+    }
+
+    /// The credit budget of each voter, if voters allocate votes quadratically
+    pub fn credits(&self) -> Option<u32> {
+        match *self {
+            VotingMethod::Quadratic { credits } => Some(credits),
+            _ => None,
+        }
+    }
+
+    /// The histogram a new or reset option starts with
+    pub fn empty_score_counts(&self) -> Vec<i32> {
+        self.max_score()
+            .map(|max_score| vec![0; max_score as usize + 1])
+            .unwrap_or_default()
+    }
+
+    /// What a valid ballot adds to the per-option totals: one vote per
+    /// selected option, the first preference only on ranked ballots, the
+    /// score given on scored ballots and the votes allocated on quadratic
+    /// ones, each multiplied by the voter's weight
+    pub fn tally_increments(&self, ballot: &Ballot) -> Vec<TallyIncrement> {
+        let weight = ballot.weight as i32;
+        if self.max_score().is_some() {
+            return ballot
+                .scores
+                .iter()
+                .map(|scored| TallyIncrement {
+                    option_id: scored.option_id,
+                    votes: scored.score as i32 * weight,
+                    score: Some(scored.score),
+                    weight,
+                })
+                .collect();
+        }
+        if self.credits().is_some() {
+            return ballot
+                .allocations
+                .iter()
+                .filter(|allocation| allocation.votes > 0)
+                .map(|allocation| TallyIncrement {
+                    option_id: allocation.option_id,
+                    votes: allocation.votes as i32 * weight,
+                    score: None,
+                    weight,
+                })
+                .collect();
+        }
+
+        let tallied = if self.is_ranked() {
+            &ballot.option_ids[..1.min(ballot.option_ids.len())]
+        } else {
+            &ballot.option_ids[..]
+        };
+        tallied
+            .iter()
+            .map(|option_id| TallyIncrement {
+                option_id: *option_id,
+                votes: weight,
+                score: None,
+                weight,
+            })
+            .collect()
+    }
+
+    /// Checks that the method can be used with `option_count` options
+    pub fn validate(&self, option_count: usize) -> Result<(), PollInputError> {
+        if option_count == 0 {
+            return Err(PollInputError::NoOptions);
+        }
+        match *self {
+            VotingMethod::MultiSelect { max_choices }
+                if max_choices == 0 || max_choices > option_count =>
+            {
+                Err(PollInputError::MaxChoices(option_count))
+            }
+            VotingMethod::Score { max_score } | VotingMethod::Star { max_score }
+                if max_score == 0 || max_score > SCORE_LIMIT =>
+            {
+                Err(PollInputError::MaxScore)
+            }
+            VotingMethod::Quadratic { credits } if credits == 0 || credits > CREDIT_LIMIT => {
+                Err(PollInputError::Credits)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks that `vote` is a valid ballot for a poll with the given options;
+    /// scored polls take `scores` alone, quadratic polls `allocations` alone
+    /// and all others a selection of options
+    pub fn validate_ballot(
+        &self,
+        vote: &VoteRequest,
+        options: &[PollOption],
+    ) -> Result<(), BallotError> {
+        let selection = vote.selection();
+        if let Some(credits) = self.credits() {
+            return validate_allocations(credits, &selection, vote, options);
+        }
+        if !vote.allocations.is_empty() {
+            return Err(BallotError::AllocationsNotAccepted);
+        }
+        if let Some(max_score) = self.max_score() {
+            return validate_scores(max_score, &selection, &vote.scores, options);
+        }
+        if !vote.scores.is_empty() {
+            return Err(BallotError::ScoresNotAccepted);
+        }
+        if selection.is_empty() {
+            return Err(BallotError::Empty);
+        }
+        for (index, option_id) in selection.iter().enumerate() {
+            if selection[..index].contains(option_id) {
+                return Err(BallotError::Duplicate(*option_id));
+            }
+            if !options.iter().any(|option| option.option_id == *option_id) {
+                return Err(BallotError::UnknownOption(*option_id));
+            }
+        }
+
+        match *self {
+            VotingMethod::SingleChoice if selection.len() != 1 => Err(BallotError::SingleChoice),
+            VotingMethod::MultiSelect { max_choices } if selection.len() > max_choices => {
+                Err(BallotError::TooManyChoices(max_choices))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Checks a scored ballot: every option scored exactly once, within range
+fn validate_scores(
+    max_score: u32,
+    selection: &[i64],
+    scores: &[OptionScore],
+    options: &[PollOption],
+) -> Result<(), BallotError> {
+    if !selection.is_empty() || scores.is_empty() {
+        return Err(BallotError::ScoresRequired);
+    }
+    for (index, scored) in scores.iter().enumerate() {
+        if scores[..index]
+            .iter()
+            .any(|earlier| earlier.option_id == scored.option_id)
+        {
+            return Err(BallotError::Duplicate(scored.option_id));
+        }
+        if !options
+            .iter()
+            .any(|option| option.option_id == scored.option_id)
+        {
+            return Err(BallotError::UnknownOption(scored.option_id));
+        }
+        if scored.score > max_score {
+            return Err(BallotError::ScoreOutOfRange(max_score));
+        }
+    }
+    match options.iter().find(|option| {
+        !scores
+            .iter()
+            .any(|scored| scored.option_id == option.option_id)
+    }) {
+        Some(option) => Err(BallotError::Unscored(option.option_id)),
+        None => Ok(()),
+    }
+}
+
+/// Checks a quadratic ballot: at least one vote, each option at most once,
+/// and the squared votes within the credit budget
+fn validate_allocations(
+    credits: u32,
+    selection: &[i64],
+    vote: &VoteRequest,
+    options: &[PollOption],
+) -> Result<(), BallotError> {
+    if !selection.is_empty() || !vote.scores.is_empty() {
+        return Err(BallotError::AllocationsRequired);
+    }
+    let allocations = &vote.allocations;
+    if allocations.iter().all(|allocation| allocation.votes == 0) {
+        return Err(BallotError::Empty);
+    }
+    for (index, allocation) in allocations.iter().enumerate() {
+        if allocations[..index]
+            .iter()
+            .any(|earlier| earlier.option_id == allocation.option_id)
+        {
+            return Err(BallotError::Duplicate(allocation.option_id));
+        }
+        if !options
+            .iter()
+            .any(|option| option.option_id == allocation.option_id)
+        {
+            return Err(BallotError::UnknownOption(allocation.option_id));
+        }
+    }
+
+    let cost: u64 = allocations
+        .iter()
+        .map(|allocation| u64::from(allocation.votes).pow(2))
+        .sum();
+    if cost > u64::from(credits) {
+        return Err(BallotError::OverBudget { cost, credits });
+    }
+    Ok(())
 }
 
 /// Represents a voting poll with its properties, options, and voting history
@@ -27,6 +403,101 @@ pub struct VotingPoll {
     pub status: PollStatus, // Using enum instead of String
     pub options: Vec<PollOption>,
     pub users_voted: Vec<String>,
+    /// Polls created before voting methods existed are single-choice
+    #[serde(default)]
+    pub voting_method: VotingMethod,
+    /// Who may vote and with what weight; anyone may vote, with weight 1, when empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub eligible_voters: Vec<EligibleVoter>,
+}
+
+impl VotingPoll {
+    /// The weight of `user_name`'s ballot, or `None` if they may not vote here
+    pub fn voter_weight(&self, user_name: &str) -> Option<u32> {
+        if self.eligible_voters.is_empty() {
+            return Some(default_weight());
+        }
+        self.eligible_voters
+            .iter()
+            .find(|voter| voter.user_name == user_name)
+            .map(|voter| voter.weight)
+    }
+}
+
+/// A poll as returned by the API, with the name its creator chose to be shown under
+#[derive(Debug, Serialize)]
+pub struct PollView {
+    #[serde(flatten)]
+    pub poll: VotingPoll,
+    /// The creator's display name, or their username when they have none
+    pub creator_display_name: String,
+}
+
+impl PollView {
+    /// Looks the creator up in a username to display name map
+    pub fn new(poll: VotingPoll, display_names: &HashMap<String, String>) -> Self {
+        let creator_display_name = display_names
+            .get(&poll.creator)
+            .cloned()
+            .unwrap_or_else(|| poll.creator.clone());
+        Self {
+            poll,
+            creator_display_name,
+        }
+    }
+}
+
+/// One voter's ballot, stored apart from the voter's name
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Ballot {
+    pub poll_id: i64,
+    /// The selected options; in order of preference on ranked polls, the
+    /// scored options on score polls
+    pub option_ids: Vec<i64>,
+    /// The score given to each option, on score polls
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub scores: Vec<OptionScore>,
+    /// The votes put on each option, on quadratic polls
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allocations: Vec<Allocation>,
+    /// How many times the ballot counts
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+    pub cast_at: DateTime<Utc>,
+}
+
+impl Ballot {
+    /// A ballot cast now from a validated vote
+    pub fn new(vote: &VoteRequest, weight: u32) -> Self {
+        let option_ids = if !vote.scores.is_empty() {
+            vote.scores.iter().map(|scored| scored.option_id).collect()
+        } else if !vote.allocations.is_empty() {
+            vote.allocations
+                .iter()
+                .filter(|allocation| allocation.votes > 0)
+                .map(|allocation| allocation.option_id)
+                .collect()
+        } else {
+            vote.selection()
+        };
+        Self {
+            poll_id: vote.poll_id,
+            option_ids,
+            scores: vote.scores.clone(),
+            allocations: vote.allocations.clone(),
+            weight,
+            cast_at: Utc::now(),
+        }
+    }
+}
+
+/// A poll's results, with the count of its individual ballots for methods that need one
+#[derive(Debug, Serialize)]
+pub struct PollResults {
+    #[serde(flatten)]
+    pub poll: PollView,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tabulation: Option<Tabulation>,
 }
 
 /// Represents the possible states of a poll
@@ -34,23 +505,70 @@ pub struct VotingPoll {
 pub enum PollStatus {
     Active,
     Expired,
+    /// Polls closed before the status was written consistently are stored as `closed`
+    #[serde(alias = "closed")]
     Closed,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct VotingPollInput {
     pub title: String,
-    pub creator: String,
     pub description: String,
     pub expiration_date: Option<DateTime<Utc>>,
     pub options: Vec<PollOptionInput>,
+    #[serde(default)]
+    pub voting_method: VotingMethod,
+    #[serde(default)]
+    pub eligible_voters: Vec<EligibleVoter>,
+}
+
+impl VotingPollInput {
+    /// Checks the voting method and eligibility list, normalizing the listed usernames
+    pub fn validate(&mut self) -> Result<(), PollInputError> {
+        self.voting_method.validate(self.options.len())?;
+
+        for index in 0..self.eligible_voters.len() {
+            let voter = &mut self.eligible_voters[index];
+            voter.user_name = normalize_username(&voter.user_name)
+                .map_err(|_| PollInputError::EligibleVoter(voter.user_name.clone()))?;
+            if voter.weight == 0 || voter.weight > WEIGHT_LIMIT {
+                return Err(PollInputError::Weight);
+            }
+            let user_name = &self.eligible_voters[index].user_name;
+            if self.eligible_voters[..index]
+                .iter()
+                .any(|earlier| &earlier.user_name == user_name)
+            {
+                return Err(PollInputError::DuplicateEligibleVoter(user_name.clone()));
+            }
+        }
+        Ok(())
+    }
 }
 
+/// A ballot; single-choice clients may send `option_id` instead of `option_ids`,
+/// score polls take `scores` and quadratic polls `allocations` instead
 #[derive(Debug, Deserialize)]
 pub struct VoteRequest {
     pub poll_id: i64,
-    pub option_id: i64,
-    pub username: String,
+    #[serde(default)]
+    pub option_id: Option<i64>,
+    #[serde(default)]
+    pub option_ids: Vec<i64>,
+    #[serde(default)]
+    pub scores: Vec<OptionScore>,
+    #[serde(default)]
+    pub allocations: Vec<Allocation>,
+}
+
+impl VoteRequest {
+    /// Every option the voter selected
+    pub fn selection(&self) -> Vec<i64> {
+        self.option_id
+            .into_iter()
+            .chain(self.option_ids.iter().copied())
+            .collect()
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -80,3 +598,265 @@ impl Stream for ServerEvents {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::tabulation::fixtures::options;
+
+    fn vote(option_ids: &[i64], allocations: &[(i64, u32)]) -> VoteRequest {
+        VoteRequest {
+            poll_id: 1,
+            option_id: None,
+            option_ids: option_ids.to_vec(),
+            scores: Vec::new(),
+            allocations: allocations
+                .iter()
+                .map(|&(option_id, votes)| Allocation { option_id, votes })
+                .collect(),
+        }
+    }
+
+    const QUADRATIC: VotingMethod = VotingMethod::Quadratic { credits: 10 };
+
+    #[test]
+    fn quadratic_ballot_may_spend_the_whole_budget() {
+        let vote = vote(&[], &[(1, 3), (2, 1), (3, 0)]);
+        assert_eq!(QUADRATIC.validate_ballot(&vote, &options(3)), Ok(()));
+    }
+
+    #[test]
+    fn quadratic_ballot_over_budget_is_rejected() {
+        let vote = vote(&[], &[(1, 3), (2, 2)]);
+        assert_eq!(
+            QUADRATIC.validate_ballot(&vote, &options(3)),
+            Err(BallotError::OverBudget {
+                cost: 13,
+                credits: 10
+            })
+        );
+    }
+
+    #[test]
+    fn quadratic_ballot_needs_allocated_votes() {
+        let options = options(3);
+        assert_eq!(
+            QUADRATIC.validate_ballot(&vote(&[], &[(1, 0)]), &options),
+            Err(BallotError::Empty)
+        );
+        assert_eq!(
+            QUADRATIC.validate_ballot(&vote(&[1], &[]), &options),
+            Err(BallotError::AllocationsRequired)
+        );
+        assert_eq!(
+            QUADRATIC.validate_ballot(&vote(&[], &[(1, 1), (1, 1)]), &options),
+            Err(BallotError::Duplicate(1))
+        );
+        assert_eq!(
+            VotingMethod::Approval.validate_ballot(&vote(&[], &[(1, 1)]), &options),
+            Err(BallotError::AllocationsNotAccepted)
+        );
+    }
+
+    #[test]
+    fn approval_ballot_may_select_every_option() {
+        let options = options(3);
+        assert_eq!(
+            VotingMethod::Approval.validate_ballot(&vote(&[1, 2, 3], &[]), &options),
+            Ok(())
+        );
+        assert_eq!(
+            VotingMethod::Approval.validate_ballot(&vote(&[1, 4], &[]), &options),
+            Err(BallotError::UnknownOption(4))
+        );
+        assert_eq!(
+            VotingMethod::Approval.validate_ballot(&vote(&[2, 2], &[]), &options),
+            Err(BallotError::Duplicate(2))
+        );
+    }
+
+    #[test]
+    fn multi_select_ballot_is_bounded_by_max_choices() {
+        let options = options(4);
+        let method = VotingMethod::MultiSelect { max_choices: 2 };
+        assert_eq!(method.validate_ballot(&vote(&[3], &[]), &options), Ok(()));
+        assert_eq!(
+            method.validate_ballot(&vote(&[1, 3], &[]), &options),
+            Ok(())
+        );
+        assert_eq!(
+            method.validate_ballot(&vote(&[1, 2, 3], &[]), &options),
+            Err(BallotError::TooManyChoices(2))
+        );
+        assert_eq!(
+            method.validate_ballot(&vote(&[], &[]), &options),
+            Err(BallotError::Empty)
+        );
+    }
+
+    #[test]
+    fn multi_select_max_choices_must_fit_the_options() {
+        for max_choices in [0, 4] {
+            assert_eq!(
+                VotingMethod::MultiSelect { max_choices }.validate(3),
+                Err(PollInputError::MaxChoices(3))
+            );
+        }
+        assert_eq!(
+            VotingMethod::MultiSelect { max_choices: 3 }.validate(3),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn single_choice_ballot_takes_exactly_one_option() {
+        let options = options(3);
+        assert_eq!(
+            VotingMethod::SingleChoice.validate_ballot(&vote(&[2], &[]), &options),
+            Ok(())
+        );
+        assert_eq!(
+            VotingMethod::SingleChoice.validate_ballot(&vote(&[1, 2], &[]), &options),
+            Err(BallotError::SingleChoice)
+        );
+    }
+
+    #[test]
+    fn quadratic_increments_are_weighted_and_skip_unused_options() {
+        let ballot = Ballot::new(&vote(&[], &[(1, 3), (2, 0), (3, 1)]), 2);
+
+        assert_eq!(ballot.option_ids, [1, 3]);
+        assert_eq!(
+            QUADRATIC.tally_increments(&ballot),
+            [
+                TallyIncrement {
+                    option_id: 1,
+                    votes: 6,
+                    score: None,
+                    weight: 2
+                },
+                TallyIncrement {
+                    option_id: 3,
+                    votes: 2,
+                    score: None,
+                    weight: 2
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn ranked_increments_count_the_weighted_first_preference() {
+        let ballot = Ballot::new(&vote(&[2, 1], &[]), 3);
+
+        assert_eq!(
+            VotingMethod::RankedChoice.tally_increments(&ballot),
+            [TallyIncrement {
+                option_id: 2,
+                votes: 3,
+                score: None,
+                weight: 3
+            }]
+        );
+    }
+
+    #[test]
+    fn score_increments_multiply_the_score_by_the_weight() {
+        let vote = VoteRequest {
+            scores: vec![OptionScore {
+                option_id: 1,
+                score: 4,
+            }],
+            ..vote(&[], &[])
+        };
+        let ballot = Ballot::new(&vote, 2);
+
+        assert_eq!(
+            VotingMethod::Score { max_score: 5 }.tally_increments(&ballot),
+            [TallyIncrement {
+                option_id: 1,
+                votes: 8,
+                score: Some(4),
+                weight: 2
+            }]
+        );
+    }
+
+    fn poll(eligible_voters: Vec<EligibleVoter>) -> VotingPoll {
+        VotingPoll {
+            poll_id: Some(1),
+            title: "Budget".to_string(),
+            creator: "alice".to_string(),
+            description: String::new(),
+            created_at: Utc::now(),
+            expiration_date: None,
+            status: PollStatus::Active,
+            options: options(2),
+            users_voted: Vec::new(),
+            voting_method: QUADRATIC,
+            eligible_voters,
+        }
+    }
+
+    #[test]
+    fn voter_weight_comes_from_the_eligibility_list() {
+        assert_eq!(poll(Vec::new()).voter_weight("anyone"), Some(1));
+
+        let poll = poll(vec![EligibleVoter {
+            user_name: "bob".to_string(),
+            weight: 3,
+        }]);
+        assert_eq!(poll.voter_weight("bob"), Some(3));
+        assert_eq!(poll.voter_weight("carol"), None);
+    }
+
+    fn input(eligible_voters: &[(&str, u32)]) -> VotingPollInput {
+        VotingPollInput {
+            title: "Budget".to_string(),
+            description: String::new(),
+            expiration_date: None,
+            options: vec![PollOptionInput {
+                text: "Parks".to_string(),
+            }],
+            voting_method: QUADRATIC,
+            eligible_voters: eligible_voters
+                .iter()
+                .map(|&(user_name, weight)| EligibleVoter {
+                    user_name: user_name.to_string(),
+                    weight,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn eligibility_list_is_normalized_and_checked() {
+        let mut valid = input(&[(" Bob ", 2)]);
+        assert!(valid.validate().is_ok());
+        assert_eq!(valid.eligible_voters[0].user_name, "bob");
+
+        assert!(matches!(
+            input(&[("bob", 0)]).validate(),
+            Err(PollInputError::Weight)
+        ));
+        assert!(matches!(
+            input(&[("bob", WEIGHT_LIMIT + 1)]).validate(),
+            Err(PollInputError::Weight)
+        ));
+        assert!(matches!(
+            input(&[("bob", 1), ("BOB", 2)]).validate(),
+            Err(PollInputError::DuplicateEligibleVoter(_))
+        ));
+    }
+
+    #[test]
+    fn credits_must_be_within_limits() {
+        assert!(VotingMethod::Quadratic { credits: 0 }.validate(1).is_err());
+        assert!(VotingMethod::Quadratic {
+            credits: CREDIT_LIMIT + 1
+        }
+        .validate(1)
+        .is_err());
+        assert!(QUADRATIC.validate(1).is_ok());
+    }
+}
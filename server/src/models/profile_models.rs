@@ -0,0 +1,263 @@
+use bson::{spec::BinarySubtype, Binary};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::models::user_models::{Role, User};
+
+/// Longest display name, in characters
+const DISPLAY_NAME_MAX_LEN: usize = 50;
+/// Longest bio, in characters
+const BIO_MAX_LEN: usize = 280;
+/// Longest timezone name, e.g. `America/Argentina/Buenos_Aires`
+const TIMEZONE_MAX_LEN: usize = 64;
+/// Longest locale tag, e.g. `zh-Hant-TW`
+const LOCALE_MAX_LEN: usize = 35;
+/// Largest avatar accepted, in bytes
+pub const AVATAR_MAX_BYTES: usize = 64 * 1024;
+
+#[derive(Debug, Error)]
+pub enum ProfileError {
+    #[error(
+        "Display name must be at most {DISPLAY_NAME_MAX_LEN} characters without control characters"
+    )]
+    DisplayName,
+    #[error("Bio must be at most {BIO_MAX_LEN} characters")]
+    Bio,
+    #[error("Timezone must be an IANA name such as Europe/Paris")]
+    Timezone,
+    #[error("Locale must be a language tag such as en-US")]
+    Locale,
+    #[error("Avatar must be at most {} KiB", AVATAR_MAX_BYTES / 1024)]
+    AvatarTooLarge,
+    #[error("Avatar must be a PNG, JPEG, GIF or WebP image")]
+    AvatarFormat,
+}
+
+/// Which notifications the user wants to receive
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NotificationPreferences {
+    /// A poll the user voted in was closed
+    pub poll_closed: bool,
+    /// Someone voted in one of the user's polls
+    pub new_votes: bool,
+}
+
+impl Default for NotificationPreferences {
+    fn default() -> Self {
+        Self {
+            poll_closed: true,
+            new_votes: false,
+        }
+    }
+}
+
+/// A small profile picture, stored inline with the user
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Avatar {
+    pub content_type: String,
+    pub data: Binary,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl Avatar {
+    /// Accepts an uploaded image, recognizing its type from its contents
+    pub fn from_upload(bytes: Vec<u8>) -> Result<Self, ProfileError> {
+        if bytes.len() > AVATAR_MAX_BYTES {
+            return Err(ProfileError::AvatarTooLarge);
+        }
+        let content_type = sniff_image_type(&bytes).ok_or(ProfileError::AvatarFormat)?;
+
+        Ok(Self {
+            content_type: content_type.to_string(),
+            data: Binary {
+                subtype: BinarySubtype::Generic,
+                bytes,
+            },
+            updated_at: Utc::now(),
+        })
+    }
+}
+
+/// The image type announced by the file's magic bytes, if it is one we serve
+fn sniff_image_type(bytes: &[u8]) -> Option<&'static str> {
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    } else if bytes.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        Some("image/jpeg")
+    } else if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        Some("image/gif")
+    } else if bytes.len() >= 12 && &bytes[..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+        Some("image/webp")
+    } else {
+        None
+    }
+}
+
+/// What the user tells others and the app about themselves
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct Profile {
+    pub display_name: Option<String>,
+    pub bio: Option<String>,
+    pub avatar: Option<Avatar>,
+    /// IANA timezone name; not checked against the timezone database
+    pub timezone: Option<String>,
+    /// BCP 47 language tag
+    pub locale: Option<String>,
+    #[serde(default)]
+    pub notifications: NotificationPreferences,
+}
+
+/// Partial update of the caller's profile; an empty string clears a field
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UpdateProfileRequest {
+    pub display_name: Option<String>,
+    pub bio: Option<String>,
+    pub timezone: Option<String>,
+    pub locale: Option<String>,
+    pub notifications: Option<NotificationPreferencesUpdate>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NotificationPreferencesUpdate {
+    pub poll_closed: Option<bool>,
+    pub new_votes: Option<bool>,
+}
+
+impl Profile {
+    /// Applies a validated update, leaving the profile untouched if any field is invalid
+    pub fn apply(&mut self, update: UpdateProfileRequest) -> Result<(), ProfileError> {
+        let mut updated = self.clone();
+
+        if let Some(display_name) = update.display_name {
+            updated.display_name = normalize_display_name(&display_name)?;
+        }
+        if let Some(bio) = update.bio {
+            let bio = bio.trim();
+            if bio.chars().count() > BIO_MAX_LEN || bio.chars().any(|c| c.is_control() && c != '\n')
+            {
+                return Err(ProfileError::Bio);
+            }
+            updated.bio = (!bio.is_empty()).then(|| bio.to_string());
+        }
+        if let Some(timezone) = update.timezone {
+            let timezone = timezone.trim();
+            if !timezone.is_empty() && !is_timezone_name(timezone) {
+                return Err(ProfileError::Timezone);
+            }
+            updated.timezone = (!timezone.is_empty()).then(|| timezone.to_string());
+        }
+        if let Some(locale) = update.locale {
+            let locale = locale.trim();
+            if !locale.is_empty() && !is_language_tag(locale) {
+                return Err(ProfileError::Locale);
+            }
+            updated.locale = (!locale.is_empty()).then(|| locale.to_string());
+        }
+        if let Some(notifications) = update.notifications {
+            if let Some(poll_closed) = notifications.poll_closed {
+                updated.notifications.poll_closed = poll_closed;
+            }
+            if let Some(new_votes) = notifications.new_votes {
+                updated.notifications.new_votes = new_votes;
+            }
+        }
+
+        *self = updated;
+        Ok(())
+    }
+}
+
+/// Trims a display name, mapping an empty one to `None`
+pub fn normalize_display_name(raw: &str) -> Result<Option<String>, ProfileError> {
+    let display_name = raw.trim();
+    if display_name.chars().count() > DISPLAY_NAME_MAX_LEN
+        || display_name.chars().any(char::is_control)
+    {
+        return Err(ProfileError::DisplayName);
+    }
+
+    Ok((!display_name.is_empty()).then(|| display_name.to_string()))
+}
+
+/// Whether `name` is shaped like an IANA timezone name (`UTC`, `Etc/GMT+5`, `Europe/Paris`)
+fn is_timezone_name(name: &str) -> bool {
+    name.len() <= TIMEZONE_MAX_LEN
+        && name.split('/').all(|part| {
+            part.chars().next().is_some_and(|c| c.is_ascii_alphabetic())
+                && part
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '+'))
+        })
+}
+
+/// Whether `tag` is shaped like a BCP 47 language tag (`en`, `en-US`, `zh-Hant-TW`)
+fn is_language_tag(tag: &str) -> bool {
+    let mut subtags = tag.split('-');
+    let language = subtags.next().unwrap_or_default();
+
+    tag.len() <= LOCALE_MAX_LEN
+        && (2..=3).contains(&language.len())
+        && language.chars().all(|c| c.is_ascii_alphabetic())
+        && subtags.all(|subtag| {
+            (1..=8).contains(&subtag.len()) && subtag.chars().all(|c| c.is_ascii_alphanumeric())
+        })
+}
+
+/// A profile as shown to its owner
+#[derive(Debug, Serialize)]
+pub struct ProfileView {
+    /// The display name, or the username when none is set
+    pub display_name: String,
+    pub bio: Option<String>,
+    pub avatar_url: Option<String>,
+    pub timezone: Option<String>,
+    pub locale: Option<String>,
+    pub notifications: NotificationPreferences,
+}
+
+impl From<&User> for ProfileView {
+    fn from(user: &User) -> Self {
+        Self {
+            display_name: user.display_name().to_string(),
+            bio: user.profile.bio.clone(),
+            avatar_url: avatar_url(user),
+            timezone: user.profile.timezone.clone(),
+            locale: user.profile.locale.clone(),
+            notifications: user.profile.notifications.clone(),
+        }
+    }
+}
+
+/// Where the user's avatar is served; the version parameter changes with every upload
+pub fn avatar_url(user: &User) -> Option<String> {
+    user.profile.avatar.as_ref().map(|avatar| {
+        format!(
+            "/api/users/{}/avatar?v={}",
+            user.user_name,
+            avatar.updated_at.timestamp()
+        )
+    })
+}
+
+/// Body of `GET /api/me`
+#[derive(Debug, Serialize)]
+pub struct MeResponse {
+    pub user_id: String,
+    pub user_name: String,
+    pub roles: Vec<Role>,
+    pub profile: ProfileView,
+}
+
+impl From<&User> for MeResponse {
+    fn from(user: &User) -> Self {
+        Self {
+            user_id: user.user_id.clone(),
+            user_name: user.user_name.clone(),
+            roles: user.roles.clone(),
+            profile: ProfileView::from(user),
+        }
+    }
+}
@@ -0,0 +1,53 @@
+use rand::{distributions::Uniform, Rng};
+use serde::{Deserialize, Serialize};
+
+/// Number of codes handed out at a time
+pub const RECOVERY_CODE_COUNT: usize = 10;
+/// Characters per half of a code; ambiguous characters (0/o, 1/l/i) are left out
+const HALF_LEN: usize = 5;
+const ALPHABET: &[u8] = b"23456789abcdefghjkmnpqrstuvwxyz";
+
+#[derive(Debug, Deserialize)]
+pub struct RecoveryRequest {
+    pub username: String,
+    pub code: String,
+}
+
+/// Freshly generated codes; shown to the user once and never again
+#[derive(Debug, Serialize)]
+pub struct RecoveryCodesResponse {
+    pub recovery_codes: Vec<String>,
+}
+
+/// Generates a set of recovery codes of the form `xxxxx-xxxxx`
+///
+/// # Returns
+/// The plain codes to hand to the user and the digests to store, in the same order
+pub fn generate_recovery_codes() -> (Vec<String>, Vec<String>) {
+    let mut rng = rand::thread_rng();
+    let index = Uniform::from(0..ALPHABET.len());
+
+    let codes: Vec<String> = (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let mut half = || -> String {
+                (0..HALF_LEN)
+                    .map(|_| ALPHABET[rng.sample(index)] as char)
+                    .collect()
+            };
+            format!("{}-{}", half(), half())
+        })
+        .collect();
+    let hashes = codes.iter().map(|code| hash_recovery_code(code)).collect();
+
+    (codes, hashes)
+}
+
+/// Digest under which a recovery code is stored; tolerant of case, spacing and the dash
+pub fn hash_recovery_code(code: &str) -> String {
+    let canonical: String = code
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    sha256::digest(canonical)
+}
@@ -0,0 +1,220 @@
+use std::env;
+use thiserror::Error;
+use webauthn_rs::prelude::*;
+use webauthn_rs_proto::AttestationConveyancePreference;
+
+#[derive(Debug, Error)]
+pub enum RelyingPartyConfigError {
+    #[error("{0} is not a valid origin: {1}")]
+    InvalidOrigin(String, String),
+    #[error("{0} uses plain http; only localhost may be served without TLS")]
+    InsecureOrigin(String),
+    #[error("WEBAUTHN_ID {rp_id} is not the domain of origin {origin} or a parent of it")]
+    ForeignOrigin { rp_id: String, origin: String },
+    #[error("No WebAuthn origin is configured")]
+    NoOrigins,
+    #[error("{0} must be one of: {1}")]
+    InvalidValue(&'static str, &'static str),
+    #[error("Invalid WebAuthn configuration: {0:?}")]
+    Webauthn(WebauthnError),
+}
+
+/// Why a newly registered passkey falls short of the attestation policy
+#[derive(Debug, Error)]
+pub enum AttestationError {
+    #[error("This authenticator did not provide an attestation")]
+    Missing,
+    #[error("This authenticator's attestation is not backed by a certificate")]
+    Uncertified,
+}
+
+/// Relying-party policy for WebAuthn ceremonies
+///
+/// Configured through the environment:
+/// * `WEBAUTHN_ID` - relying party ID, a registrable domain (default `localhost`)
+/// * `WEBAUTHN_ORIGIN` - primary origin (default `http://localhost:3000`)
+/// * `WEBAUTHN_EXTRA_ORIGINS` - comma-separated further origins, e.g. a staging subdomain
+/// * `WEBAUTHN_ALLOW_SUBDOMAINS` - accept any subdomain of the allowed origins
+/// * `WEBAUTHN_ALLOW_ANY_PORT` - ignore the port when matching origins
+/// * `WEBAUTHN_RP_NAME` - name shown by authenticators (defaults to the RP ID)
+/// * `WEBAUTHN_ATTESTATION` - `none` (default), `indirect` or `direct`; see [`Self::check_attestation`]
+/// * `WEBAUTHN_AUTHENTICATOR_ATTACHMENT` - `platform` or `cross-platform`; any if unset
+#[derive(Debug, Clone)]
+pub struct RelyingPartyConfig {
+    pub rp_id: String,
+    pub rp_name: Option<String>,
+    pub origins: Vec<Url>,
+    pub allow_subdomains: bool,
+    pub allow_any_port: bool,
+    pub attestation: AttestationConveyancePreference,
+    pub authenticator_attachment: Option<AuthenticatorAttachment>,
+}
+
+impl RelyingPartyConfig {
+    pub fn from_env() -> Result<Self, RelyingPartyConfigError> {
+        let rp_id = env::var("WEBAUTHN_ID").unwrap_or_else(|_| "localhost".to_string());
+        let primary_origin =
+            env::var("WEBAUTHN_ORIGIN").unwrap_or_else(|_| "http://localhost:3000".to_string());
+        let extra_origins = env::var("WEBAUTHN_EXTRA_ORIGINS").unwrap_or_default();
+
+        let origins = std::iter::once(primary_origin.as_str())
+            .chain(extra_origins.split(','))
+            .map(str::trim)
+            .filter(|origin| !origin.is_empty())
+            .map(|origin| Self::parse_origin(origin, &rp_id))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let attestation = match env::var("WEBAUTHN_ATTESTATION").as_deref() {
+            Err(_) | Ok("") | Ok("none") => AttestationConveyancePreference::None,
+            Ok("indirect") => AttestationConveyancePreference::Indirect,
+            Ok("direct") => AttestationConveyancePreference::Direct,
+            Ok(_) => {
+                return Err(RelyingPartyConfigError::InvalidValue(
+                    "WEBAUTHN_ATTESTATION",
+                    "none, indirect, direct",
+                ))
+            }
+        };
+        let authenticator_attachment =
+            match env::var("WEBAUTHN_AUTHENTICATOR_ATTACHMENT").as_deref() {
+                Err(_) | Ok("") => None,
+                Ok("platform") => Some(AuthenticatorAttachment::Platform),
+                Ok("cross-platform") => Some(AuthenticatorAttachment::CrossPlatform),
+                Ok(_) => {
+                    return Err(RelyingPartyConfigError::InvalidValue(
+                        "WEBAUTHN_AUTHENTICATOR_ATTACHMENT",
+                        "platform, cross-platform",
+                    ))
+                }
+            };
+
+        Ok(Self {
+            rp_id,
+            rp_name: env::var("WEBAUTHN_RP_NAME")
+                .ok()
+                .filter(|name| !name.is_empty()),
+            origins,
+            allow_subdomains: Self::flag("WEBAUTHN_ALLOW_SUBDOMAINS")?,
+            allow_any_port: Self::flag("WEBAUTHN_ALLOW_ANY_PORT")?,
+            attestation,
+            authenticator_attachment,
+        })
+    }
+
+    fn flag(name: &'static str) -> Result<bool, RelyingPartyConfigError> {
+        match env::var(name).as_deref() {
+            Err(_) | Ok("") | Ok("false") => Ok(false),
+            Ok("true") => Ok(true),
+            Ok(_) => Err(RelyingPartyConfigError::InvalidValue(name, "true, false")),
+        }
+    }
+
+    /// Parses an origin and checks that it belongs to the relying party
+    fn parse_origin(origin: &str, rp_id: &str) -> Result<Url, RelyingPartyConfigError> {
+        let url = Url::parse(origin).map_err(|e| {
+            RelyingPartyConfigError::InvalidOrigin(origin.to_string(), e.to_string())
+        })?;
+        let domain = url.domain().ok_or_else(|| {
+            RelyingPartyConfigError::InvalidOrigin(origin.to_string(), "no domain".to_string())
+        })?;
+
+        match url.scheme() {
+            "https" => {}
+            "http" if domain == "localhost" => {}
+            "http" => return Err(RelyingPartyConfigError::InsecureOrigin(origin.to_string())),
+            scheme => {
+                return Err(RelyingPartyConfigError::InvalidOrigin(
+                    origin.to_string(),
+                    format!("unsupported scheme {}", scheme),
+                ))
+            }
+        }
+
+        if domain != rp_id && !domain.ends_with(&format!(".{}", rp_id)) {
+            return Err(RelyingPartyConfigError::ForeignOrigin {
+                rp_id: rp_id.to_string(),
+                origin: origin.to_string(),
+            });
+        }
+
+        Ok(url)
+    }
+
+    /// Whether a browser `Origin` header names one of the allowed origins, honouring
+    /// the subdomain and port relaxations
+    pub fn allows_origin(&self, origin: &str) -> bool {
+        let Ok(origin) = Url::parse(origin) else {
+            return false;
+        };
+        let Some(host) = origin.host_str() else {
+            return false;
+        };
+
+        self.origins.iter().any(|allowed| {
+            let allowed_host = allowed.host_str().unwrap_or_default();
+            allowed.scheme() == origin.scheme()
+                && (host == allowed_host
+                    || (self.allow_subdomains && host.ends_with(&format!(".{}", allowed_host))))
+                && (self.allow_any_port
+                    || allowed.port_or_known_default() == origin.port_or_known_default())
+        })
+    }
+
+    /// Builds the `Webauthn` instance for this relying party
+    pub fn build_webauthn(&self) -> Result<Webauthn, RelyingPartyConfigError> {
+        let (primary_origin, extra_origins) = self
+            .origins
+            .split_first()
+            .ok_or(RelyingPartyConfigError::NoOrigins)?;
+        let mut builder = WebauthnBuilder::new(&self.rp_id, primary_origin)
+            .map_err(RelyingPartyConfigError::Webauthn)?
+            .allow_subdomains(self.allow_subdomains)
+            .allow_any_port(self.allow_any_port);
+        for origin in extra_origins {
+            builder = builder.append_allowed_origin(origin);
+        }
+        if let Some(rp_name) = &self.rp_name {
+            builder = builder.rp_name(rp_name);
+        }
+
+        builder.build().map_err(RelyingPartyConfigError::Webauthn)
+    }
+
+    /// Applies the registration policy to a creation challenge
+    ///
+    /// Credentials are always requested as resident (discoverable), so the passkey can
+    /// later be used for usernameless sign-in.
+    pub fn apply_registration_policy(&self, challenge_response: &mut CreationChallengeResponse) {
+        let options = &mut challenge_response.public_key;
+        options.attestation = Some(self.attestation.clone());
+
+        if let Some(selection) = options.authenticator_selection.as_mut() {
+            selection.require_resident_key = true;
+            selection.authenticator_attachment = self.authenticator_attachment;
+        }
+    }
+
+    /// Checks a passkey that just finished registration against the attestation preference
+    ///
+    /// Registration has already verified the signature of any attestation statement the
+    /// authenticator returned; this checks that the statement is there at all. `indirect`
+    /// accepts any statement, including self attestation, while `direct` needs one backed
+    /// by an attestation certificate. Certificates are not chained to vendor roots.
+    pub fn check_attestation(&self, passkey: &Passkey) -> Result<(), AttestationError> {
+        let credential = Credential::from(passkey.clone());
+        match (&self.attestation, &credential.attestation.data) {
+            (AttestationConveyancePreference::None, _) => Ok(()),
+            (_, ParsedAttestationData::None | ParsedAttestationData::Uncertain) => {
+                Err(AttestationError::Missing)
+            }
+            (AttestationConveyancePreference::Indirect, _) => Ok(()),
+            (
+                AttestationConveyancePreference::Direct,
+                ParsedAttestationData::Basic(_)
+                | ParsedAttestationData::AttCa(_)
+                | ParsedAttestationData::AnonCa(_),
+            ) => Ok(()),
+            (AttestationConveyancePreference::Direct, _) => Err(AttestationError::Uncertified),
+        }
+    }
+}
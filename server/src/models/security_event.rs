@@ -0,0 +1,47 @@
+use log::{info, warn};
+
+/// Security-relevant incidents that need attention beyond a failed request
+#[derive(Debug, Clone)]
+pub enum SecurityEvent {
+    /// The authenticator's signature counter did not increase, which may mean
+    /// the credential has been cloned
+    CounterRegression {
+        user_id: String,
+        credential_id: String,
+    },
+    /// A recovery code was redeemed to enroll a new passkey
+    RecoveryCodeUsed { user_id: String, remaining: usize },
+    /// A recovery attempt presented an unknown or already used code
+    RecoveryCodeRejected { username: String },
+    /// The user replaced their recovery codes with a new set
+    RecoveryCodesRegenerated { user_id: String },
+}
+
+/// Reports a security event on the dedicated `security` log target
+pub fn report(event: &SecurityEvent) {
+    match event {
+        SecurityEvent::CounterRegression {
+            user_id,
+            credential_id,
+        } => warn!(
+            target: "security",
+            "Possible cloned authenticator: counter regression for credential {} of user {}",
+            credential_id, user_id
+        ),
+        SecurityEvent::RecoveryCodeUsed { user_id, remaining } => warn!(
+            target: "security",
+            "Recovery code used by user {}, {} codes remaining",
+            user_id, remaining
+        ),
+        SecurityEvent::RecoveryCodeRejected { username } => warn!(
+            target: "security",
+            "Rejected recovery attempt for username {}",
+            username
+        ),
+        SecurityEvent::RecoveryCodesRegenerated { user_id } => info!(
+            target: "security",
+            "Recovery codes regenerated for user {}",
+            user_id
+        ),
+    }
+}
@@ -0,0 +1,135 @@
+use actix_session::Session;
+use actix_web::{
+    cookie::{time, Cookie, SameSite},
+    http::Method,
+    HttpRequest,
+};
+use log::warn;
+use std::env;
+use uuid::Uuid;
+
+use crate::models::auth_jwt::refresh_token_ttl;
+
+/// Session key holding the access token of a cookie-mode sign-in
+pub const ACCESS_TOKEN_KEY: &str = "access_token";
+/// Session key holding the refresh token of a cookie-mode sign-in
+pub const REFRESH_TOKEN_KEY: &str = "refresh_token";
+/// Session key holding the CSRF token the client must echo back
+pub const CSRF_TOKEN_KEY: &str = "csrf_token";
+/// Script-readable cookie carrying the CSRF token
+pub const CSRF_COOKIE: &str = "csrf_token";
+/// Header cookie-mode clients echo the CSRF token in on state-changing requests
+pub const CSRF_HEADER: &str = "X-CSRF-Token";
+
+/// How a signed-in browser holds on to its tokens
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SessionMode {
+    /// Tokens are returned in the response body and sent back as `Authorization: Bearer`
+    Bearer,
+    /// Tokens stay in the HttpOnly session cookie; state-changing requests need a CSRF token
+    Cookie,
+}
+
+impl SessionMode {
+    /// Reads `SESSION_MODE` (`bearer` or `cookie`), falling back to bearer tokens
+    pub fn from_env() -> Self {
+        let mode = match env::var("SESSION_MODE").as_deref() {
+            Err(_) | Ok("") | Ok("bearer") => SessionMode::Bearer,
+            Ok("cookie") => SessionMode::Cookie,
+            Ok(value) => {
+                warn!("Invalid SESSION_MODE value {:?}, using bearer", value);
+                SessionMode::Bearer
+            }
+        };
+        if mode == SessionMode::Cookie && cross_site_sessions() {
+            warn!("SESSION_CROSS_SITE is ignored in cookie mode, whose CSRF defence needs SameSite=Strict");
+        }
+        mode
+    }
+
+    /// SameSite policy of the session cookie
+    ///
+    /// Cookie mode requires the client to be served from the same site as the
+    /// API. In bearer mode the cookie only carries WebAuthn ceremony state and
+    /// is Lax, unless `SESSION_CROSS_SITE=true` lets a client on another site
+    /// keep it.
+    pub fn same_site(self) -> SameSite {
+        match self {
+            SessionMode::Bearer if cross_site_sessions() => SameSite::None,
+            SessionMode::Bearer => SameSite::Lax,
+            SessionMode::Cookie => SameSite::Strict,
+        }
+    }
+
+    /// How long the session cookie outlives the browser session, if at all
+    pub fn session_ttl(self) -> Option<time::Duration> {
+        match self {
+            SessionMode::Bearer => None,
+            SessionMode::Cookie => Some(time::Duration::seconds(refresh_token_ttl().num_seconds())),
+        }
+    }
+}
+
+/// Whether `SESSION_CROSS_SITE` asks for a session cookie sent on cross-site requests
+fn cross_site_sessions() -> bool {
+    match env::var("SESSION_CROSS_SITE").as_deref() {
+        Err(_) | Ok("") | Ok("false") => false,
+        Ok("true") => true,
+        Ok(value) => {
+            warn!("Invalid SESSION_CROSS_SITE value {:?}, using false", value);
+            false
+        }
+    }
+}
+
+/// Generates a new CSRF token
+pub fn generate_csrf_token() -> String {
+    Uuid::new_v4().simple().to_string()
+}
+
+/// The script-readable cookie the client copies into the CSRF header
+pub fn csrf_cookie(token: &str) -> Cookie<'static> {
+    let mut cookie = Cookie::build(CSRF_COOKIE, token.to_string())
+        .path("/")
+        .secure(true)
+        .http_only(false)
+        .same_site(SameSite::Strict)
+        .finish();
+    if let Some(ttl) = SessionMode::Cookie.session_ttl() {
+        cookie.set_max_age(ttl);
+    }
+    cookie
+}
+
+/// Expires the CSRF cookie on sign-out
+pub fn removal_csrf_cookie() -> Cookie<'static> {
+    let mut cookie = csrf_cookie("");
+    cookie.make_removal();
+    cookie
+}
+
+/// Double-submit check for a request authenticated by the session cookie
+///
+/// Safe methods always pass. Otherwise the CSRF header must match the CSRF
+/// cookie, and both must match the token bound to the session.
+pub fn verify_csrf(req: &HttpRequest, session: &Session) -> bool {
+    if matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS) {
+        return true;
+    }
+
+    let Some(header) = req
+        .headers()
+        .get(CSRF_HEADER)
+        .and_then(|header| header.to_str().ok())
+    else {
+        return false;
+    };
+    let Some(cookie) = req.cookie(CSRF_COOKIE) else {
+        return false;
+    };
+    let Ok(Some(expected)) = session.get::<String>(CSRF_TOKEN_KEY) else {
+        return false;
+    };
+
+    !expected.is_empty() && header == cookie.value() && header == expected
+}
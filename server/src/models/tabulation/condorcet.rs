@@ -0,0 +1,403 @@
+use serde::Serialize;
+use std::cmp::Reverse;
+
+use crate::models::poll_models::{Ballot, PollOption};
+use crate::models::tabulation::{rankings, Tabulation, Tabulator};
+
+/// Head-to-head counts between every pair of options
+#[derive(Debug, Serialize, Clone)]
+pub struct PairwiseMatrix {
+    /// Option ids in poll order; rows and columns of the matrix follow this order
+    pub options: Vec<i64>,
+    /// `preferences[i][j]` is the number of ballots ranking `options[i]` above `options[j]`.
+    /// A ranked option is preferred to every option its ballot leaves unranked,
+    /// and unranked options are level with one another.
+    pub preferences: Vec<Vec<usize>>,
+}
+
+impl PairwiseMatrix {
+    pub fn from_ballots(options: &[PollOption], ballots: &[Vec<i64>]) -> Self {
+        let options: Vec<i64> = options.iter().map(|option| option.option_id).collect();
+        let mut preferences = vec![vec![0; options.len()]; options.len()];
+
+        for ballot in ballots {
+            let rank = |option_id: i64| ballot.iter().position(|ranked| *ranked == option_id);
+            let ranks: Vec<Option<usize>> = options.iter().map(|id| rank(*id)).collect();
+            for (i, rank_i) in ranks.iter().enumerate() {
+                for (j, rank_j) in ranks.iter().enumerate() {
+                    let preferred = match (rank_i, rank_j) {
+                        (Some(rank_i), Some(rank_j)) => rank_i < rank_j,
+                        (Some(_), None) => true,
+                        (None, _) => false,
+                    };
+                    if preferred {
+                        preferences[i][j] += 1;
+                    }
+                }
+            }
+        }
+
+        Self {
+            options,
+            preferences,
+        }
+    }
+
+    /// Whether more ballots rank option `i` above option `j` than the other way around
+    fn beats(&self, i: usize, j: usize) -> bool {
+        self.preferences[i][j] > self.preferences[j][i]
+    }
+
+    /// The option that beats every other option head to head, if there is one
+    pub fn condorcet_winner(&self) -> Option<i64> {
+        (0..self.options.len())
+            .find(|&i| (0..self.options.len()).all(|j| i == j || self.beats(i, j)))
+            .map(|i| self.options[i])
+    }
+
+    /// The smallest group of options that each beat every option outside it
+    ///
+    /// A single member is the Condorcet winner. Several members are in a
+    /// cycle, or level head to head, so no option beats all the others.
+    pub fn smith_set(&self) -> Vec<i64> {
+        let n = self.options.len();
+        // reaches[i][j]: a chain of options, each not losing to the next, leads from i to j
+        let mut reaches: Vec<Vec<bool>> = (0..n)
+            .map(|i| (0..n).map(|j| !self.beats(j, i)).collect())
+            .collect();
+        for k in 0..n {
+            for i in 0..n {
+                for j in 0..n {
+                    if reaches[i][k] && reaches[k][j] {
+                        reaches[i][j] = true;
+                    }
+                }
+            }
+        }
+
+        (0..n)
+            .filter(|&i| reaches[i].iter().all(|reached| *reached))
+            .map(|i| self.options[i])
+            .collect()
+    }
+}
+
+/// Pairwise count with the winner found by the Schulze method
+#[derive(Debug, Serialize, Clone)]
+pub struct Schulze {
+    pub pairwise: PairwiseMatrix,
+    /// `strongest_paths[i][j]` is the strength of the strongest path from
+    /// `options[i]` to `options[j]`, a path being as strong as its weakest win
+    pub strongest_paths: Vec<Vec<usize>>,
+    pub condorcet_winner: Option<i64>,
+    pub smith_set: Vec<i64>,
+    /// Options no other option beats by strongest path; several when they are
+    /// tied, none when no ballot has been cast
+    pub winners: Vec<i64>,
+}
+
+/// Counts ranked ballots by the Schulze method
+///
+/// A win of `a` over `b` is as strong as the number of ballots ranking `a`
+/// above `b`. `a` beats `b` by strongest path when the strongest chain of wins
+/// leading from `a` to `b` is stronger than the strongest leading back.
+pub fn schulze(options: &[PollOption], ballots: &[Vec<i64>]) -> Schulze {
+    let pairwise = PairwiseMatrix::from_ballots(options, ballots);
+    let n = pairwise.options.len();
+
+    let mut strongest_paths: Vec<Vec<usize>> = (0..n)
+        .map(|i| {
+            (0..n)
+                .map(|j| {
+                    if pairwise.beats(i, j) {
+                        pairwise.preferences[i][j]
+                    } else {
+                        0
+                    }
+                })
+                .collect()
+        })
+        .collect();
+    for k in 0..n {
+        for i in (0..n).filter(|&i| i != k) {
+            for j in (0..n).filter(|&j| j != k && j != i) {
+                let through_k = strongest_paths[i][k].min(strongest_paths[k][j]);
+                if through_k > strongest_paths[i][j] {
+                    strongest_paths[i][j] = through_k;
+                }
+            }
+        }
+    }
+
+    let winners = if ballots.is_empty() {
+        Vec::new()
+    } else {
+        (0..n)
+            .filter(|&i| (0..n).all(|j| strongest_paths[i][j] >= strongest_paths[j][i]))
+            .map(|i| pairwise.options[i])
+            .collect()
+    };
+
+    Schulze {
+        condorcet_winner: pairwise.condorcet_winner(),
+        smith_set: pairwise.smith_set(),
+        pairwise,
+        strongest_paths,
+        winners,
+    }
+}
+
+/// One option's head-to-head win over another
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+pub struct Majority {
+    pub winner: i64,
+    pub loser: i64,
+    /// Ballots ranking the winner above the loser
+    pub votes_for: usize,
+    /// Ballots ranking the loser above the winner
+    pub votes_against: usize,
+}
+
+/// Pairwise count with the winner found by ranked pairs
+#[derive(Debug, Serialize, Clone)]
+pub struct RankedPairs {
+    pub pairwise: PairwiseMatrix,
+    pub condorcet_winner: Option<i64>,
+    pub smith_set: Vec<i64>,
+    /// Majorities locked in, strongest first
+    pub locked: Vec<Majority>,
+    /// Majorities passed over because they would have closed a cycle
+    pub skipped: Vec<Majority>,
+    /// Options no locked majority points to; several only when options are
+    /// level head to head, none when no ballot has been cast
+    pub winners: Vec<i64>,
+}
+
+/// Counts ranked ballots by ranked pairs
+///
+/// Majorities are taken strongest first and locked in unless they would close
+/// a cycle with those already locked. A majority is stronger when more ballots
+/// support it, then when fewer oppose it; majorities that are still level are
+/// taken in poll order of the winner, then of the loser.
+pub fn ranked_pairs(options: &[PollOption], ballots: &[Vec<i64>]) -> RankedPairs {
+    let pairwise = PairwiseMatrix::from_ballots(options, ballots);
+    let n = pairwise.options.len();
+
+    let mut majorities: Vec<(usize, usize)> = (0..n)
+        .flat_map(|i| (0..n).map(move |j| (i, j)))
+        .filter(|&(i, j)| pairwise.beats(i, j))
+        .collect();
+    majorities.sort_by_key(|&(i, j)| {
+        (
+            Reverse(pairwise.preferences[i][j]),
+            pairwise.preferences[j][i],
+            i,
+            j,
+        )
+    });
+
+    let mut locked_edges: Vec<(usize, usize)> = Vec::new();
+    let mut locked = Vec::new();
+    let mut skipped = Vec::new();
+    for (i, j) in majorities {
+        let majority = Majority {
+            winner: pairwise.options[i],
+            loser: pairwise.options[j],
+            votes_for: pairwise.preferences[i][j],
+            votes_against: pairwise.preferences[j][i],
+        };
+        if leads_to(&locked_edges, j, i) {
+            skipped.push(majority);
+        } else {
+            locked_edges.push((i, j));
+            locked.push(majority);
+        }
+    }
+
+    let winners = if ballots.is_empty() {
+        Vec::new()
+    } else {
+        (0..n)
+            .filter(|&i| locked_edges.iter().all(|&(_, loser)| loser != i))
+            .map(|i| pairwise.options[i])
+            .collect()
+    };
+
+    RankedPairs {
+        condorcet_winner: pairwise.condorcet_winner(),
+        smith_set: pairwise.smith_set(),
+        pairwise,
+        locked,
+        skipped,
+        winners,
+    }
+}
+
+/// Whether the locked majorities form a chain from option `from` to option `to`
+fn leads_to(edges: &[(usize, usize)], from: usize, to: usize) -> bool {
+    let mut stack = vec![from];
+    let mut visited = vec![from];
+    while let Some(current) = stack.pop() {
+        if current == to {
+            return true;
+        }
+        for &(winner, loser) in edges {
+            if winner == current && !visited.contains(&loser) {
+                visited.push(loser);
+                stack.push(loser);
+            }
+        }
+    }
+    false
+}
+
+/// Counts ranked ballots by the Schulze method
+pub struct SchulzeTabulator;
+
+impl Tabulator for SchulzeTabulator {
+    fn tabulate(&self, options: &[PollOption], ballots: &[Ballot]) -> Tabulation {
+        Tabulation::Schulze(schulze(options, &rankings(ballots)))
+    }
+}
+
+/// Counts ranked ballots by ranked pairs
+pub struct RankedPairsTabulator;
+
+impl Tabulator for RankedPairsTabulator {
+    fn tabulate(&self, options: &[PollOption], ballots: &[Ballot]) -> Tabulation {
+        Tabulation::RankedPairs(ranked_pairs(options, &rankings(ballots)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::tabulation::fixtures::{ballots, options};
+
+    /// 1 beats 2 by 6–3, 2 beats 3 by 7–2 and 3 beats 1 by 5–4
+    fn cycle() -> Vec<Vec<i64>> {
+        ballots(&[(4, &[1, 2, 3]), (3, &[2, 3, 1]), (2, &[3, 1, 2])])
+    }
+
+    #[test]
+    fn pairwise_counts_ranked_options_above_unranked_ones() {
+        let pairwise =
+            PairwiseMatrix::from_ballots(&options(3), &ballots(&[(2, &[2]), (1, &[1, 3])]));
+
+        assert_eq!(pairwise.preferences, [[0, 1, 1], [2, 0, 2], [0, 1, 0]]);
+        assert_eq!(pairwise.condorcet_winner(), Some(2));
+        assert_eq!(pairwise.smith_set(), [2]);
+    }
+
+    #[test]
+    fn schulze_resolves_a_cycle_by_strongest_paths() {
+        let count = schulze(&options(3), &cycle());
+
+        assert_eq!(count.condorcet_winner, None);
+        assert_eq!(count.smith_set, [1, 2, 3]);
+        assert_eq!(count.strongest_paths, [[0, 6, 6], [5, 0, 7], [5, 5, 0]]);
+        assert_eq!(count.winners, [1]);
+    }
+
+    // The example election from Schulze's paper, options 1 to 5 standing for A to E
+    #[test]
+    fn schulze_matches_the_published_example() {
+        let count = schulze(
+            &options(5),
+            &ballots(&[
+                (5, &[1, 3, 2, 5, 4]),
+                (5, &[1, 4, 5, 3, 2]),
+                (8, &[2, 5, 4, 1, 3]),
+                (3, &[3, 1, 2, 5, 4]),
+                (7, &[3, 1, 5, 2, 4]),
+                (2, &[3, 2, 1, 4, 5]),
+                (7, &[4, 3, 5, 2, 1]),
+                (8, &[5, 2, 1, 4, 3]),
+            ]),
+        );
+
+        assert_eq!(
+            count.strongest_paths,
+            [
+                [0, 28, 28, 30, 24],
+                [25, 0, 28, 33, 24],
+                [25, 29, 0, 29, 24],
+                [25, 28, 28, 0, 24],
+                [25, 28, 28, 31, 0],
+            ]
+        );
+        assert_eq!(count.condorcet_winner, None);
+        assert_eq!(count.winners, [5]);
+    }
+
+    #[test]
+    fn ranked_pairs_skips_the_majority_closing_a_cycle() {
+        let count = ranked_pairs(&options(3), &cycle());
+
+        assert_eq!(
+            count.locked,
+            [
+                Majority {
+                    winner: 2,
+                    loser: 3,
+                    votes_for: 7,
+                    votes_against: 2
+                },
+                Majority {
+                    winner: 1,
+                    loser: 2,
+                    votes_for: 6,
+                    votes_against: 3
+                },
+            ]
+        );
+        assert_eq!(
+            count.skipped,
+            [Majority {
+                winner: 3,
+                loser: 1,
+                votes_for: 5,
+                votes_against: 4
+            }]
+        );
+        assert_eq!(count.winners, [1]);
+    }
+
+    #[test]
+    fn ranked_pairs_takes_fewer_opposing_votes_as_stronger() {
+        // 1 beats 2 and 3 by 3–2, 2 beats 3 by 3–1 with one ballot ranking neither
+        let count = ranked_pairs(
+            &options(3),
+            &ballots(&[(1, &[1]), (2, &[1, 2, 3]), (1, &[2, 3, 1]), (1, &[3, 2, 1])]),
+        );
+
+        assert_eq!(
+            count.locked[0],
+            Majority {
+                winner: 2,
+                loser: 3,
+                votes_for: 3,
+                votes_against: 1
+            }
+        );
+        assert!(count.skipped.is_empty());
+        assert_eq!(count.winners, [1]);
+    }
+
+    #[test]
+    fn options_level_head_to_head_share_the_win() {
+        let ballots = ballots(&[(1, &[1, 2]), (1, &[2, 1])]);
+
+        assert_eq!(schulze(&options(2), &ballots).winners, [1, 2]);
+        let count = ranked_pairs(&options(2), &ballots);
+        assert!(count.locked.is_empty());
+        assert_eq!(count.smith_set, [1, 2]);
+        assert_eq!(count.winners, [1, 2]);
+    }
+
+    #[test]
+    fn no_ballots_has_no_winner() {
+        assert!(schulze(&options(2), &[]).winners.is_empty());
+        assert!(ranked_pairs(&options(2), &[]).winners.is_empty());
+    }
+}
@@ -0,0 +1,24 @@
+//! Polls and ballots shared by the tabulation tests
+
+use crate::models::poll_models::PollOption;
+
+/// Options with ids `1..=count`, in poll order
+pub fn options(count: i64) -> Vec<PollOption> {
+    (1..=count)
+        .map(|option_id| PollOption {
+            option_id,
+            text: format!("Option {}", option_id),
+            votes: 0,
+            voters: Some(0),
+            score_counts: Vec::new(),
+        })
+        .collect()
+}
+
+/// Ranked ballots, each ranking repeated as many times as its count
+pub fn ballots(groups: &[(usize, &[i64])]) -> Vec<Vec<i64>> {
+    groups
+        .iter()
+        .flat_map(|(count, ranking)| std::iter::repeat_n(ranking.to_vec(), *count))
+        .collect()
+}
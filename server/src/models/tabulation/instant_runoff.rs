@@ -0,0 +1,277 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use crate::models::poll_models::{Ballot, PollOption};
+use crate::models::tabulation::{rankings, Tabulation, Tabulator};
+
+/// Votes held by one option in a round
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+pub struct OptionTally {
+    pub option_id: i64,
+    pub votes: usize,
+}
+
+/// Ballots moved from an eliminated option to their next continuing preference
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+pub struct Transfer {
+    pub from: i64,
+    /// `None` when the ballots rank no continuing option and are exhausted
+    pub to: Option<i64>,
+    pub votes: usize,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct RunoffRound {
+    pub round: usize,
+    /// Votes of every option still in the count, in poll order
+    pub tallies: Vec<OptionTally>,
+    /// Ballots that rank none of the continuing options
+    pub exhausted: usize,
+    /// The option knocked out at the end of this round, if the count went on
+    pub eliminated: Option<i64>,
+    /// Whether the elimination had to be decided by the tie-break rule
+    pub tie_break: bool,
+    pub transfers: Vec<Transfer>,
+}
+
+/// Round-by-round instant-runoff count
+#[derive(Debug, Serialize, Clone)]
+pub struct InstantRunoff {
+    pub rounds: Vec<RunoffRound>,
+    /// `None` only when no ballot has been cast
+    pub winner: Option<i64>,
+}
+
+/// Counts ranked ballots by instant runoff
+///
+/// Each round, every ballot counts for its highest-ranked option still in the
+/// count. An option holding more than half of the non-exhausted ballots wins;
+/// otherwise the option with the fewest votes is eliminated and its ballots
+/// move to their next preference. Options are eliminated one per round.
+///
+/// Ties for last place are broken deterministically: the tied option with
+/// fewer votes in the latest earlier round where the tied options differ is
+/// eliminated; if they were level in every round, the one listed last on the
+/// poll (highest `option_id`) is eliminated.
+pub fn instant_runoff(options: &[PollOption], ballots: &[Vec<i64>]) -> InstantRunoff {
+    let mut continuing: Vec<i64> = options.iter().map(|option| option.option_id).collect();
+    continuing.sort_unstable();
+    let mut history: Vec<BTreeMap<i64, usize>> = Vec::new();
+    let mut rounds = Vec::new();
+
+    loop {
+        let mut tally: BTreeMap<i64, usize> =
+            continuing.iter().map(|option_id| (*option_id, 0)).collect();
+        let mut exhausted = 0;
+        for ballot in ballots {
+            match top_choice(ballot, &continuing) {
+                Some(option_id) => *tally.entry(option_id).or_default() += 1,
+                None => exhausted += 1,
+            }
+        }
+        let active = ballots.len() - exhausted;
+
+        let tallies = options
+            .iter()
+            .filter_map(|option| {
+                tally.get(&option.option_id).map(|votes| OptionTally {
+                    option_id: option.option_id,
+                    votes: *votes,
+                })
+            })
+            .collect();
+        let mut round = RunoffRound {
+            round: rounds.len() + 1,
+            tallies,
+            exhausted,
+            eliminated: None,
+            tie_break: false,
+            transfers: Vec::new(),
+        };
+
+        if active == 0 {
+            rounds.push(round);
+            return InstantRunoff {
+                rounds,
+                winner: None,
+            };
+        }
+        if let Some((option_id, _)) = tally.iter().find(|(_, votes)| **votes * 2 > active) {
+            let winner = Some(*option_id);
+            rounds.push(round);
+            return InstantRunoff { rounds, winner };
+        }
+        if continuing.len() == 1 {
+            let winner = continuing.first().copied();
+            rounds.push(round);
+            return InstantRunoff { rounds, winner };
+        }
+
+        let fewest = tally.values().copied().min().unwrap_or_default();
+        let mut lowest: Vec<i64> = tally
+            .iter()
+            .filter(|(_, votes)| **votes == fewest)
+            .map(|(option_id, _)| *option_id)
+            .collect();
+        round.tie_break = lowest.len() > 1;
+        for earlier in history.iter().rev() {
+            if lowest.len() == 1 {
+                break;
+            }
+            let fewest = lowest
+                .iter()
+                .map(|id| earlier[id])
+                .min()
+                .unwrap_or_default();
+            lowest.retain(|id| earlier[id] == fewest);
+        }
+        let eliminated = *lowest.iter().max().expect("a continuing option");
+
+        // Move the eliminated option's ballots on to their next continuing preference
+        continuing.retain(|option_id| *option_id != eliminated);
+        let mut transfers: BTreeMap<Option<i64>, usize> = BTreeMap::new();
+        for ballot in ballots {
+            let position = ballot.iter().position(|option_id| *option_id == eliminated);
+            let counted_for_eliminated = position.is_some_and(|position| {
+                ballot[..position]
+                    .iter()
+                    .all(|option_id| !continuing.contains(option_id))
+            });
+            if counted_for_eliminated {
+                *transfers
+                    .entry(top_choice(ballot, &continuing))
+                    .or_default() += 1;
+            }
+        }
+
+        round.eliminated = Some(eliminated);
+        round.transfers = transfers
+            .into_iter()
+            .map(|(to, votes)| Transfer {
+                from: eliminated,
+                to,
+                votes,
+            })
+            .collect();
+        rounds.push(round);
+        history.push(tally);
+    }
+}
+
+/// Counts ranked ballots by instant runoff
+pub struct InstantRunoffTabulator;
+
+impl Tabulator for InstantRunoffTabulator {
+    fn tabulate(&self, options: &[PollOption], ballots: &[Ballot]) -> Tabulation {
+        Tabulation::InstantRunoff(instant_runoff(options, &rankings(ballots)))
+    }
+}
+
+/// The highest-ranked option on `ballot` that is still in the count
+fn top_choice(ballot: &[i64], continuing: &[i64]) -> Option<i64> {
+    ballot
+        .iter()
+        .copied()
+        .find(|option_id| continuing.contains(option_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::tabulation::fixtures::{ballots, options};
+
+    fn votes(round: &RunoffRound) -> Vec<(i64, usize)> {
+        round
+            .tallies
+            .iter()
+            .map(|tally| (tally.option_id, tally.votes))
+            .collect()
+    }
+
+    #[test]
+    fn transfers_eliminated_ballots_to_next_preference() {
+        let count = instant_runoff(
+            &options(3),
+            &ballots(&[(8, &[1]), (5, &[2, 3]), (4, &[3, 2])]),
+        );
+
+        assert_eq!(count.rounds.len(), 2);
+        assert_eq!(votes(&count.rounds[0]), [(1, 8), (2, 5), (3, 4)]);
+        assert_eq!(count.rounds[0].eliminated, Some(3));
+        assert!(!count.rounds[0].tie_break);
+        assert_eq!(
+            count.rounds[0].transfers,
+            [Transfer {
+                from: 3,
+                to: Some(2),
+                votes: 4
+            }]
+        );
+        assert_eq!(votes(&count.rounds[1]), [(1, 8), (2, 9)]);
+        assert_eq!(count.winner, Some(2));
+    }
+
+    #[test]
+    fn first_round_majority_wins_outright() {
+        let count = instant_runoff(&options(3), &ballots(&[(3, &[1, 2]), (2, &[2])]));
+
+        assert_eq!(count.rounds.len(), 1);
+        assert_eq!(count.rounds[0].eliminated, None);
+        assert_eq!(count.winner, Some(1));
+    }
+
+    #[test]
+    fn exhausted_ballots_leave_the_majority_threshold() {
+        let count = instant_runoff(&options(3), &ballots(&[(4, &[1]), (3, &[2]), (2, &[3])]));
+
+        assert_eq!(count.rounds[0].eliminated, Some(3));
+        assert_eq!(
+            count.rounds[0].transfers,
+            [Transfer {
+                from: 3,
+                to: None,
+                votes: 2
+            }]
+        );
+        // 4 of the 7 ballots still in the count is a majority
+        assert_eq!(count.rounds[1].exhausted, 2);
+        assert_eq!(count.winner, Some(1));
+    }
+
+    #[test]
+    fn tie_for_last_is_broken_by_earlier_rounds() {
+        let count = instant_runoff(
+            &options(4),
+            &ballots(&[(5, &[1]), (3, &[2]), (4, &[3]), (1, &[4, 2])]),
+        );
+
+        assert_eq!(count.rounds[0].eliminated, Some(4));
+        assert_eq!(votes(&count.rounds[1]), [(1, 5), (2, 4), (3, 4)]);
+        // Options 2 and 3 are level, but 2 had fewer votes in round 1
+        assert!(count.rounds[1].tie_break);
+        assert_eq!(count.rounds[1].eliminated, Some(2));
+        assert_eq!(count.rounds[2].exhausted, 4);
+        assert_eq!(count.winner, Some(1));
+    }
+
+    #[test]
+    fn tie_level_in_every_round_eliminates_the_last_listed_option() {
+        let count = instant_runoff(
+            &options(3),
+            &ballots(&[(3, &[1]), (2, &[2, 1]), (2, &[3, 2])]),
+        );
+
+        assert!(count.rounds[0].tie_break);
+        assert_eq!(count.rounds[0].eliminated, Some(3));
+        assert_eq!(votes(&count.rounds[1]), [(1, 3), (2, 4)]);
+        assert_eq!(count.winner, Some(2));
+    }
+
+    #[test]
+    fn no_ballots_has_no_winner() {
+        let count = instant_runoff(&options(2), &[]);
+
+        assert_eq!(count.rounds.len(), 1);
+        assert_eq!(count.winner, None);
+    }
+}
@@ -0,0 +1,93 @@
+pub mod condorcet;
+#[cfg(test)]
+pub(crate) mod fixtures;
+pub mod instant_runoff;
+pub mod score;
+
+use serde::Serialize;
+
+use crate::models::poll_models::{Ballot, PollOption, VotingMethod};
+use condorcet::{RankedPairs, RankedPairsTabulator, Schulze, SchulzeTabulator};
+use instant_runoff::{InstantRunoff, InstantRunoffTabulator};
+use score::{Score, ScoreTabulator, Star, StarTabulator};
+
+/// How a poll's ballots were counted, for methods that are more than a tally per option
+#[derive(Debug, Serialize, Clone)]
+#[serde(tag = "method", rename_all = "snake_case")]
+pub enum Tabulation {
+    InstantRunoff(InstantRunoff),
+    Schulze(Schulze),
+    RankedPairs(RankedPairs),
+    Score(Score),
+    Star(Star),
+}
+
+/// Works a poll's results out from its individual ballots, each counted as
+/// many times as its weight
+pub trait Tabulator: Send + Sync {
+    fn tabulate(&self, options: &[PollOption], ballots: &[Ballot]) -> Tabulation;
+}
+
+/// The option ids of each ballot, in the order the voter selected them,
+/// repeated once per unit of the ballot's weight
+fn rankings(ballots: &[Ballot]) -> Vec<Vec<i64>> {
+    ballots
+        .iter()
+        .flat_map(|ballot| std::iter::repeat_n(&ballot.option_ids, ballot.weight as usize))
+        .cloned()
+        .collect()
+}
+
+/// The tabulator for polls using `method`
+///
+/// # Returns
+/// `None` for methods whose per-option tallies are the result
+pub fn tabulator(method: &VotingMethod) -> Option<Box<dyn Tabulator>> {
+    match *method {
+        VotingMethod::RankedChoice => Some(Box::new(InstantRunoffTabulator)),
+        VotingMethod::Schulze => Some(Box::new(SchulzeTabulator)),
+        VotingMethod::RankedPairs => Some(Box::new(RankedPairsTabulator)),
+        VotingMethod::Score { max_score } => Some(Box::new(ScoreTabulator { max_score })),
+        VotingMethod::Star { max_score } => Some(Box::new(StarTabulator { max_score })),
+        VotingMethod::SingleChoice
+        | VotingMethod::Approval
+        | VotingMethod::MultiSelect { .. }
+        | VotingMethod::Quadratic { .. } => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn ballot(weight: u32, option_ids: &[i64]) -> Ballot {
+        Ballot {
+            poll_id: 1,
+            option_ids: option_ids.to_vec(),
+            scores: Vec::new(),
+            allocations: Vec::new(),
+            weight,
+            cast_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn rankings_repeat_each_ballot_by_its_weight() {
+        assert_eq!(
+            rankings(&[ballot(2, &[1, 2]), ballot(1, &[2])]),
+            [vec![1, 2], vec![1, 2], vec![2]]
+        );
+    }
+
+    #[test]
+    fn weighted_ballot_outweighs_more_voters() {
+        let ballots = [ballot(3, &[2]), ballot(1, &[1]), ballot(1, &[1])];
+
+        let tabulator = tabulator(&VotingMethod::RankedChoice).expect("ranked polls are tabulated");
+        match tabulator.tabulate(&fixtures::options(2), &ballots) {
+            Tabulation::InstantRunoff(count) => assert_eq!(count.winner, Some(2)),
+            other => panic!("unexpected tabulation {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,319 @@
+use serde::Serialize;
+use std::cmp::Reverse;
+
+use crate::models::poll_models::{Ballot, PollOption};
+use crate::models::tabulation::instant_runoff::OptionTally;
+use crate::models::tabulation::{Tabulation, Tabulator};
+
+/// The scores one option received
+#[derive(Debug, Serialize, Clone)]
+pub struct ScoreSummary {
+    pub option_id: i64,
+    pub total: u64,
+    /// Weighted mean score; `None` when no ballot has been cast
+    pub average: Option<f64>,
+    /// Weighted count of ballots giving each score, indexed by score
+    pub distribution: Vec<usize>,
+}
+
+/// Score count, the winner being the option with the highest total
+#[derive(Debug, Serialize, Clone)]
+pub struct Score {
+    /// Every option, highest total first
+    pub scores: Vec<ScoreSummary>,
+    /// `None` only when no ballot has been cast
+    pub winner: Option<i64>,
+}
+
+/// STAR's head-to-head runoff between the two highest-scoring options
+#[derive(Debug, Serialize, Clone)]
+pub struct StarRunoff {
+    /// Weighted count of ballots scoring each finalist above the other
+    pub preferences: Vec<OptionTally>,
+    /// Weighted count of ballots scoring both finalists the same
+    pub no_preference: usize,
+}
+
+/// Score count followed by an automatic runoff between the top two
+#[derive(Debug, Serialize, Clone)]
+pub struct Star {
+    /// Every option, highest total first
+    pub scores: Vec<ScoreSummary>,
+    /// The two highest-scoring options, or the only option of a one-option poll
+    pub finalists: Vec<i64>,
+    /// `None` when there are fewer than two finalists
+    pub runoff: Option<StarRunoff>,
+    /// `None` only when no ballot has been cast
+    pub winner: Option<i64>,
+}
+
+/// Sums and histograms each option's scores, ordering options highest total first
+///
+/// Options with the same total are ordered by how many ballots gave them the
+/// top score, then by poll order.
+pub fn score_summaries(
+    max_score: u32,
+    options: &[PollOption],
+    ballots: &[Ballot],
+) -> Vec<ScoreSummary> {
+    let total_weight: u64 = ballots.iter().map(|ballot| u64::from(ballot.weight)).sum();
+    let mut summaries: Vec<ScoreSummary> = options
+        .iter()
+        .map(|option| {
+            let mut distribution = vec![0; max_score as usize + 1];
+            let mut total = 0;
+            for ballot in ballots {
+                let score = score_of(ballot, option.option_id);
+                distribution[score.min(max_score) as usize] += ballot.weight as usize;
+                total += u64::from(score) * u64::from(ballot.weight);
+            }
+            ScoreSummary {
+                option_id: option.option_id,
+                total,
+                average: (total_weight > 0).then(|| total as f64 / total_weight as f64),
+                distribution,
+            }
+        })
+        .collect();
+
+    // The sort is stable, so options still level keep their poll order
+    summaries.sort_by_key(|summary| {
+        (
+            Reverse(summary.total),
+            Reverse(summary.distribution[max_score as usize]),
+        )
+    });
+    summaries
+}
+
+/// Counts score ballots; the option with the highest total wins
+pub fn score(max_score: u32, options: &[PollOption], ballots: &[Ballot]) -> Score {
+    let scores = score_summaries(max_score, options, ballots);
+    let winner = if ballots.is_empty() {
+        None
+    } else {
+        scores.first().map(|summary| summary.option_id)
+    };
+
+    Score { scores, winner }
+}
+
+/// Counts score ballots by STAR (score then automatic runoff)
+///
+/// The two options with the highest totals are the finalists. The finalist
+/// scored higher on more ballots wins; if the runoff is level, the finalist
+/// with the higher total wins, as ordered by [`score_summaries`].
+pub fn star(max_score: u32, options: &[PollOption], ballots: &[Ballot]) -> Star {
+    let scores = score_summaries(max_score, options, ballots);
+    let finalists: Vec<i64> = scores
+        .iter()
+        .take(2)
+        .map(|summary| summary.option_id)
+        .collect();
+
+    let runoff = match finalists[..] {
+        [first, second] => {
+            let (mut first_votes, mut second_votes, mut no_preference) = (0, 0, 0);
+            for ballot in ballots {
+                let weight = ballot.weight as usize;
+                let (first_score, second_score) =
+                    (score_of(ballot, first), score_of(ballot, second));
+                if first_score > second_score {
+                    first_votes += weight;
+                } else if second_score > first_score {
+                    second_votes += weight;
+                } else {
+                    no_preference += weight;
+                }
+            }
+            Some(StarRunoff {
+                preferences: vec![
+                    OptionTally {
+                        option_id: first,
+                        votes: first_votes,
+                    },
+                    OptionTally {
+                        option_id: second,
+                        votes: second_votes,
+                    },
+                ],
+                no_preference,
+            })
+        }
+        _ => None,
+    };
+
+    let winner = if ballots.is_empty() {
+        None
+    } else {
+        match &runoff {
+            Some(runoff) => runoff
+                .preferences
+                .iter()
+                .max_by_key(|tally| (tally.votes, tally.option_id == finalists[0]))
+                .map(|tally| tally.option_id),
+            None => finalists.first().copied(),
+        }
+    };
+
+    Star {
+        scores,
+        finalists,
+        runoff,
+        winner,
+    }
+}
+
+/// The score `ballot` gave an option
+fn score_of(ballot: &Ballot, option_id: i64) -> u32 {
+    ballot
+        .scores
+        .iter()
+        .find(|scored| scored.option_id == option_id)
+        .map(|scored| scored.score)
+        .unwrap_or_default()
+}
+
+/// Counts score ballots by total score
+pub struct ScoreTabulator {
+    pub max_score: u32,
+}
+
+impl Tabulator for ScoreTabulator {
+    fn tabulate(&self, options: &[PollOption], ballots: &[Ballot]) -> Tabulation {
+        Tabulation::Score(score(self.max_score, options, ballots))
+    }
+}
+
+/// Counts score ballots by STAR
+pub struct StarTabulator {
+    pub max_score: u32,
+}
+
+impl Tabulator for StarTabulator {
+    fn tabulate(&self, options: &[PollOption], ballots: &[Ballot]) -> Tabulation {
+        Tabulation::Star(star(self.max_score, options, ballots))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::poll_models::OptionScore;
+    use crate::models::tabulation::fixtures::options;
+    use chrono::Utc;
+
+    fn ballot(weight: u32, scores: &[u32]) -> Ballot {
+        let scores: Vec<OptionScore> = scores
+            .iter()
+            .enumerate()
+            .map(|(index, score)| OptionScore {
+                option_id: index as i64 + 1,
+                score: *score,
+            })
+            .collect();
+        Ballot {
+            poll_id: 1,
+            option_ids: scores.iter().map(|scored| scored.option_id).collect(),
+            scores,
+            allocations: Vec::new(),
+            weight,
+            cast_at: Utc::now(),
+        }
+    }
+
+    /// Option 2 has the highest total, but more weight scores 3 above it
+    fn election() -> Vec<Ballot> {
+        vec![
+            ballot(1, &[5, 3, 0]),
+            ballot(3, &[0, 4, 5]),
+            ballot(1, &[5, 2, 1]),
+        ]
+    }
+
+    #[test]
+    fn summaries_total_and_histogram_weighted_scores() {
+        let summaries = score_summaries(5, &options(3), &election());
+
+        let totals: Vec<(i64, u64)> = summaries
+            .iter()
+            .map(|summary| (summary.option_id, summary.total))
+            .collect();
+        assert_eq!(totals, [(2, 17), (3, 16), (1, 10)]);
+        assert_eq!(summaries[0].average, Some(3.4));
+        assert_eq!(summaries[0].distribution, [0, 0, 1, 1, 3, 0]);
+        assert_eq!(summaries[1].distribution, [1, 1, 0, 0, 0, 3]);
+        assert_eq!(summaries[2].distribution, [3, 0, 0, 0, 0, 2]);
+    }
+
+    #[test]
+    fn level_totals_are_ordered_by_top_scores() {
+        let summaries = score_summaries(5, &options(2), &[ballot(1, &[3, 5]), ballot(1, &[3, 1])]);
+
+        assert_eq!(summaries[0].option_id, 2);
+        assert_eq!(summaries[0].total, summaries[1].total);
+    }
+
+    #[test]
+    fn score_winner_has_the_highest_total() {
+        assert_eq!(score(5, &options(3), &election()).winner, Some(2));
+    }
+
+    #[test]
+    fn star_runoff_can_overturn_the_score_leader() {
+        let count = star(5, &options(3), &election());
+
+        assert_eq!(count.finalists, [2, 3]);
+        let runoff = count.runoff.expect("two finalists");
+        assert_eq!(
+            runoff.preferences,
+            [
+                OptionTally {
+                    option_id: 2,
+                    votes: 2
+                },
+                OptionTally {
+                    option_id: 3,
+                    votes: 3
+                },
+            ]
+        );
+        assert_eq!(runoff.no_preference, 0);
+        assert_eq!(count.winner, Some(3));
+    }
+
+    #[test]
+    fn level_star_runoff_goes_to_the_higher_total() {
+        let count = star(
+            5,
+            &options(3),
+            &[
+                ballot(1, &[5, 3, 0]),
+                ballot(2, &[0, 4, 5]),
+                ballot(1, &[5, 2, 1]),
+                ballot(1, &[0, 2, 2]),
+            ],
+        );
+
+        assert_eq!(count.finalists, [2, 3]);
+        let runoff = count.runoff.expect("two finalists");
+        assert_eq!(runoff.no_preference, 1);
+        assert_eq!(count.winner, Some(2));
+    }
+
+    #[test]
+    fn one_option_star_has_no_runoff() {
+        let count = star(5, &options(1), &[ballot(1, &[4])]);
+
+        assert!(count.runoff.is_none());
+        assert_eq!(count.winner, Some(1));
+    }
+
+    #[test]
+    fn no_ballots_has_no_winner() {
+        let count = score(5, &options(2), &[]);
+        assert_eq!(count.winner, None);
+        assert_eq!(count.scores[0].average, None);
+        assert_eq!(star(5, &options(2), &[]).winner, None);
+    }
+}
@@ -0,0 +1,52 @@
+use mongodb::bson::DateTime;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// A refresh token as stored server-side; only the SHA-256 digest of the token is kept
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RefreshToken {
+    pub token_hash: String,
+    pub user_id: String,
+    /// Shared by every token descended from the same login, so a replayed token
+    /// can revoke the whole rotation chain
+    pub family_id: String,
+    pub created_at: DateTime,
+    pub expires_at: DateTime,
+    pub used: bool,
+    pub revoked: bool,
+}
+
+/// An access token revoked before its expiry, identified by its `jti` claim
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RevokedToken {
+    pub jti: String,
+    pub user_id: String,
+    pub expires_at: DateTime,
+}
+
+/// Access tokens issued to `user_id` before `not_before` are no longer accepted
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TokenCutoff {
+    pub user_id: String,
+    pub not_before: DateTime,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RefreshRequest {
+    pub refresh_token: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LogoutRequest {
+    pub refresh_token: Option<String>,
+}
+
+/// Generates a new opaque refresh token
+pub fn generate_refresh_token() -> String {
+    format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple())
+}
+
+/// Digest under which a refresh token is stored and looked up
+pub fn hash_refresh_token(token: &str) -> String {
+    sha256::digest(token)
+}
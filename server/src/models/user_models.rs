@@ -1,11 +1,128 @@
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use unicode_normalization::UnicodeNormalization;
 
 use webauthn_rs::prelude::*;
 
+use crate::models::profile_models::Profile;
+
+/// Bounds on the length of a normalized username, in characters
+const USERNAME_MIN_LEN: usize = 3;
+pub(crate) const USERNAME_MAX_LEN: usize = 32;
+
+/// Names that could be mistaken for the service itself
+const RESERVED_USERNAMES: &[&str] = &[
+    "admin",
+    "administrator",
+    "root",
+    "system",
+    "support",
+    "security",
+    "moderator",
+    "api",
+    "auth",
+    "me",
+    "null",
+    "undefined",
+    "anonymous",
+];
+
+#[derive(Debug, Error)]
+pub enum UsernameError {
+    #[error("Username must be between {USERNAME_MIN_LEN} and {USERNAME_MAX_LEN} characters")]
+    Length,
+    #[error("Username may only contain letters, digits, '.', '-' and '_', and must start with a letter or digit")]
+    Charset,
+    #[error("Username is reserved")]
+    Reserved,
+}
+
+/// Brings a username into the canonical form it is stored and looked up under
+///
+/// The name is NFKC-normalized and lowercased, so visually identical or
+/// differently-cased spellings map to the same account.
+///
+/// # Returns
+/// The canonical username, or why the name is not acceptable
+pub fn normalize_username(raw: &str) -> Result<String, UsernameError> {
+    let username: String = raw.trim().nfkc().collect::<String>().to_lowercase();
+
+    let len = username.chars().count();
+    if !(USERNAME_MIN_LEN..=USERNAME_MAX_LEN).contains(&len) {
+        return Err(UsernameError::Length);
+    }
+
+    let valid_start = username
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphanumeric());
+    let valid_chars = username
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_'));
+    if !valid_start || !valid_chars {
+        return Err(UsernameError::Charset);
+    }
+
+    if RESERVED_USERNAMES.contains(&username.as_str()) {
+        return Err(UsernameError::Reserved);
+    }
+
+    Ok(username)
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Votes {
     pub poll_id: i64,
+    /// The first selected option
     pub option_id: i64,
+    /// Every selected option; empty on votes recorded before multi-select polls
+    #[serde(default)]
+    pub option_ids: Vec<i64>,
+}
+
+impl Votes {
+    /// Records a ballot; `selection` must not be empty
+    pub fn new(poll_id: i64, selection: Vec<i64>) -> Self {
+        Self {
+            poll_id,
+            option_id: selection[0],
+            option_ids: selection,
+        }
+    }
+}
+
+/// Roles a user can hold; admins may manage any poll
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    User,
+    Admin,
+}
+
+fn default_roles() -> Vec<Role> {
+    vec![Role::User]
+}
+
+/// User-facing metadata for one of the user's passkeys, keyed by credential ID
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CredentialInfo {
+    pub credential_id: String,
+    pub nickname: Option<String>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+impl CredentialInfo {
+    /// Metadata for a passkey registered just now
+    pub fn new(passkey: &Passkey, nickname: Option<String>) -> Self {
+        Self {
+            credential_id: passkey.cred_id().to_string(),
+            nickname,
+            created_at: Some(Utc::now()),
+            last_used_at: None,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -15,4 +132,44 @@ pub struct User {
     pub polls_voted: Option<Vec<Votes>>,
     pub owned_polls: Option<Vec<i64>>,
     pub keys: Vec<Passkey>,
+    #[serde(default = "default_roles")]
+    pub roles: Vec<Role>,
+    /// Metadata for `keys`; passkeys registered before it existed have no entry
+    #[serde(default)]
+    pub credentials: Vec<CredentialInfo>,
+    /// SHA-256 digests of the unused recovery codes
+    #[serde(default)]
+    pub recovery_codes: Vec<String>,
+    #[serde(default)]
+    pub profile: Profile,
+}
+
+impl User {
+    /// The name shown to other users, falling back to the username
+    pub fn display_name(&self) -> &str {
+        self.profile
+            .display_name
+            .as_deref()
+            .unwrap_or(&self.user_name)
+    }
+
+    /// Metadata for every passkey, filling in blanks for keys that have no entry
+    pub fn credential_infos(&self) -> Vec<CredentialInfo> {
+        self.keys
+            .iter()
+            .map(|key| {
+                let credential_id = key.cred_id().to_string();
+                self.credentials
+                    .iter()
+                    .find(|info| info.credential_id == credential_id)
+                    .cloned()
+                    .unwrap_or(CredentialInfo {
+                        credential_id,
+                        nickname: None,
+                        created_at: None,
+                        last_used_at: None,
+                    })
+            })
+            .collect()
+    }
 }
@@ -0,0 +1,86 @@
+use std::env;
+use std::time::Duration;
+
+use actix_web::web::Data;
+use log::{error, info, warn};
+
+use crate::models::{
+    authentication_state::AuthenticationState, registration_state::RegistrationState,
+};
+
+/// Timing configuration for WebAuthn ceremonies and the reaper that sweeps them
+#[derive(Clone, Copy, Debug)]
+pub struct CeremonyConfig {
+    pub ttl: Duration,
+    pub sweep_interval: Duration,
+}
+
+impl CeremonyConfig {
+    /// Reads `CEREMONY_TTL_SECS` and `CEREMONY_SWEEP_INTERVAL_SECS`, falling back to defaults
+    pub fn from_env() -> Self {
+        Self {
+            ttl: Self::secs_from_env("CEREMONY_TTL_SECS", 300),
+            sweep_interval: Self::secs_from_env("CEREMONY_SWEEP_INTERVAL_SECS", 60),
+        }
+    }
+
+    fn secs_from_env(name: &str, default: u64) -> Duration {
+        let secs = match env::var(name) {
+            Ok(value) => match value.parse::<u64>() {
+                Ok(secs) if secs > 0 => secs,
+                _ => {
+                    warn!("Invalid {} value {:?}, using {}s", name, value, default);
+                    default
+                }
+            },
+            Err(_) => default,
+        };
+
+        Duration::from_secs(secs)
+    }
+}
+
+/// Sweeps both ceremony stores once, logging how many abandoned ceremonies were dropped
+async fn sweep(reg_state: &RegistrationState, auth_state: &AuthenticationState) {
+    match reg_state.cleanup_expired().await {
+        Ok(0) => {}
+        Ok(swept) => info!("Swept {} expired registration ceremonies", swept),
+        Err(e) => error!("Failed to sweep registration ceremonies: {}", e),
+    }
+
+    match auth_state.cleanup_expired().await {
+        Ok(0) => {}
+        Ok(swept) => info!("Swept {} expired authentication ceremonies", swept),
+        Err(e) => error!("Failed to sweep authentication ceremonies: {}", e),
+    }
+}
+
+/// Spawns the ceremony reaper, restarting it if it ever panics
+pub fn spawn(
+    config: CeremonyConfig,
+    reg_state: Data<RegistrationState>,
+    auth_state: Data<AuthenticationState>,
+) {
+    tokio::spawn(async move {
+        loop {
+            let reg_state = reg_state.clone();
+            let auth_state = auth_state.clone();
+
+            let reaper = tokio::spawn(async move {
+                let mut interval = tokio::time::interval(config.sweep_interval);
+                loop {
+                    interval.tick().await;
+                    sweep(reg_state.as_ref(), auth_state.as_ref()).await;
+                }
+            });
+
+            match reaper.await {
+                Err(e) if e.is_panic() => {
+                    error!("Ceremony reaper panicked, restarting: {}", e);
+                    tokio::time::sleep(config.sweep_interval).await;
+                }
+                _ => break,
+            }
+        }
+    });
+}
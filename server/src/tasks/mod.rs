@@ -0,0 +1 @@
+pub mod ceremony_reaper;